@@ -2,7 +2,10 @@ use std::fs;
 use std::path::PathBuf;
 
 use criterion::{criterion_group, criterion_main, Criterion};
-use osmnodecache::{CacheStore, DenseFileCacheOpts};
+use dashmap::DashMap;
+use memmap2::MmapMut;
+use osmnodecache::{Cache, CacheStore, DenseFileCache, DenseFileCacheOpts, HashMapCache};
+use rayon::iter::{IntoParallelRefIterator, ParallelIterator};
 
 fn bench_crate(c: &mut Criterion) {
     c.bench_function("bench", |b| {
@@ -21,7 +24,116 @@ fn bench_crate(c: &mut Criterion) {
         });
         let _ = fs::remove_file(test_file);
     });
+
+    // Grows the file in many small steps, dominated by the cost of growing the memory map.
+    // A cache opened from the file extends its map in place with mremap on Linux, while a cache
+    // created with `from_mmap` always flushes (unless disabled) and re-creates the map, same as other platforms.
+    for (name, recreate, flush_on_grow) in [
+        ("grow", false, true),
+        ("grow_recreate", true, true),
+        ("grow_recreate_no_flush", true, false),
+    ] {
+        c.bench_function(name, |b| {
+            let test_file = "./dense_file_grow.dat";
+            b.iter(|| {
+                let _ = fs::remove_file(test_file);
+                let opts = DenseFileCacheOpts::new(PathBuf::from(test_file))
+                    .page_size(64 * 1024)
+                    .flush_on_grow(flush_on_grow);
+                let fc = if recreate {
+                    let file = fs::OpenOptions::new()
+                        .read(true)
+                        .write(true)
+                        .create(true)
+                        .truncate(true)
+                        .open(test_file)
+                        .unwrap();
+                    file.set_len(64 * 1024).unwrap();
+                    DenseFileCache::from_mmap(unsafe { MmapMut::map_mut(&file).unwrap() }, opts)
+                } else {
+                    opts.open().unwrap()
+                };
+                let mut cache = fc.get_accessor();
+                for v in 0..1_000_000 {
+                    cache.set(v, v as u64);
+                }
+            });
+            let _ = fs::remove_file(test_file);
+        });
+    }
+}
+
+/// Compare setting values through the boxed `dyn Cache` accessor with the concrete one that can be inlined
+fn bench_accessor(c: &mut Criterion) {
+    let test_file = "./dense_file_accessor.dat";
+    let _ = fs::remove_file(test_file);
+    let fc = DenseFileCacheOpts::new(PathBuf::from(test_file))
+        .page_size(1024 * 1024)
+        .open()
+        .unwrap();
+    c.bench_function("dense_set_boxed", |b| {
+        let mut cache = fc.get_accessor();
+        b.iter(|| {
+            for v in 0..100_000 {
+                cache.set(v, v as u64);
+            }
+        });
+    });
+    c.bench_function("dense_set_inlined", |b| {
+        let mut cache = fc.accessor();
+        b.iter(|| {
+            for v in 0..100_000 {
+                cache.set(v, v as u64);
+            }
+        });
+    });
+    drop(fc);
+    let _ = fs::remove_file(test_file);
+}
+
+fn bench_hashmap(c: &mut Criterion) {
+    // Ids spread over a large range, similar to the nodes of an OSM extract
+    let ids: Vec<usize> = (0..100_000).map(|v| v * 37 + 11).collect();
+    // Same workload on a plain `DashMap` with the default SipHash hasher, as a baseline for the Fx hasher
+    c.bench_function("hashmap_set", |b| {
+        b.iter(|| {
+            let mut cache = HashMapCache::new();
+            for &id in &ids {
+                cache.set(id, id as u64);
+            }
+        });
+    });
+    c.bench_function("dashmap_siphash_set", |b| {
+        b.iter(|| {
+            let map = DashMap::<u64, u64>::new();
+            for &id in &ids {
+                map.insert(id as u64, id as u64);
+            }
+        });
+    });
+    let items: Vec<(u64, u64)> = ids.iter().map(|&id| (id as u64, id as u64)).collect();
+    c.bench_function("hashmap_par_extend", |b| {
+        b.iter(|| HashMapCache::new().par_extend(items.par_iter().copied()));
+    });
+    c.bench_function("hashmap_get", |b| {
+        let mut cache = HashMapCache::new();
+        for &id in &ids {
+            cache.set(id, id as u64);
+        }
+        b.iter(|| ids.iter().map(|&id| cache.get(id)).sum::<u64>());
+    });
+    c.bench_function("dashmap_siphash_get", |b| {
+        let map = DashMap::<u64, u64>::new();
+        for &id in &ids {
+            map.insert(id as u64, id as u64);
+        }
+        b.iter(|| {
+            ids.iter()
+                .map(|&id| map.get(&(id as u64)).map_or(0, |v| *v))
+                .sum::<u64>()
+        });
+    });
 }
 
-criterion_group!(benches, bench_crate);
+criterion_group!(benches, bench_crate, bench_accessor, bench_hashmap);
 criterion_main!(benches);
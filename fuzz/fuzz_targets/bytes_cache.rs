@@ -0,0 +1,29 @@
+//! Read random indexes from a `BytesCache` over a random buffer.
+//! The first 8 bytes of the input pick the indexes, the rest is the cache content.
+//! Reads beyond the buffer must return `None` instead of reading past its end.
+#![no_main]
+
+use std::sync::Arc;
+
+use libfuzzer_sys::fuzz_target;
+use osmnodecache::{BytesCache, Cache, Endian};
+
+fuzz_target!(|data: &[u8]| {
+    if data.len() < 8 {
+        return;
+    }
+    let (seed, content) = data.split_at(8);
+    let seed = u64::from_le_bytes(seed.try_into().unwrap()) as usize;
+    let cache = BytesCache::from_bytes(Arc::new(content.to_vec())).endianness(Endian::Big);
+    let len = cache.len();
+    assert_eq!(len, content.len() / 8);
+    for index in [seed, seed % (len + 1), len, usize::MAX, usize::MAX / 8 + 1] {
+        let value = cache.try_get(index);
+        if index < len {
+            // `get` panics beyond the buffer, which the fuzzer would report as a crash
+            assert_eq!(cache.get(index), value.unwrap_or(0));
+        } else {
+            assert_eq!(value, None);
+        }
+    }
+});
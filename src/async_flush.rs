@@ -0,0 +1,105 @@
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll, Waker};
+use std::thread;
+
+use crate::{DenseFileCache, OsmNodeCacheResult};
+
+#[derive(Default)]
+struct State {
+    result: Option<OsmNodeCacheResult<()>>,
+    waker: Option<Waker>,
+}
+
+/// Resolves once the background flush thread has stored its result
+struct FlushFuture {
+    state: Arc<Mutex<State>>,
+}
+
+impl Future for FlushFuture {
+    type Output = OsmNodeCacheResult<()>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let mut state = self.state.lock().unwrap();
+        match state.result.take() {
+            Some(result) => Poll::Ready(result),
+            None => {
+                state.waker = Some(cx.waker().clone());
+                Poll::Pending
+            }
+        }
+    }
+}
+
+impl DenseFileCache {
+    /// Flush all outstanding changes to disk on a separate thread, so that an async executor
+    /// is not blocked while waiting for `msync`. The returned future does not depend on any runtime.
+    /// The flush starts right away, even if the future is never polled.
+    pub fn flush_async_spawn(&self) -> impl Future<Output = OsmNodeCacheResult<()>> {
+        let state = Arc::new(Mutex::new(State::default()));
+        let cache = self.clone();
+        let thread_state = Arc::clone(&state);
+        thread::spawn(move || {
+            let result = cache.flush();
+            let mut state = thread_state.lock().unwrap();
+            state.result = Some(result);
+            if let Some(waker) = state.waker.take() {
+                waker.wake();
+            }
+        });
+        FlushFuture { state }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::future::Future;
+    use std::path::PathBuf;
+    use std::sync::Arc;
+    use std::task::{Context, Poll, Wake};
+    use std::thread::{self, Thread};
+
+    use crate::*;
+
+    struct ThreadWaker(Thread);
+
+    impl Wake for ThreadWaker {
+        fn wake(self: Arc<Self>) {
+            self.0.unpark();
+        }
+    }
+
+    /// Minimal executor to poll the future to completion without an async runtime
+    fn block_on<F: Future>(future: F) -> F::Output {
+        let mut future = Box::pin(future);
+        let waker = Arc::new(ThreadWaker(thread::current())).into();
+        let mut cx = Context::from_waker(&waker);
+        loop {
+            match future.as_mut().poll(&mut cx) {
+                Poll::Ready(result) => return result,
+                Poll::Pending => thread::park(),
+            }
+        }
+    }
+
+    #[test]
+    fn flush_async_spawn() {
+        let test_file = "./flush_async_spawn.dat";
+        let _ = fs::remove_file(test_file);
+        {
+            let fc = DenseFileCacheOpts::new(PathBuf::from(test_file))
+                .page_size(80)
+                .open()
+                .unwrap();
+            let mut cache = fc.get_accessor();
+            cache.set(3, 30);
+            drop(cache);
+            block_on(fc.flush_async_spawn()).unwrap();
+            let data = fs::read(test_file).unwrap();
+            assert_eq!(&data[24..32], &30_u64.to_ne_bytes());
+        }
+        let _ = fs::remove_file(test_file);
+    }
+}
@@ -0,0 +1,192 @@
+use std::io;
+use std::panic::resume_unwind;
+use std::sync::mpsc::{sync_channel, Receiver, SyncSender};
+use std::thread::{self, JoinHandle};
+
+use crate::traits::CacheStore;
+use crate::{DenseFileCache, OsmNodeCacheResult};
+
+enum Message {
+    Set(usize, u64),
+    Flush(SyncSender<OsmNodeCacheResult<()>>),
+}
+
+/// A write buffer in front of a [`DenseFileCache`]: values are sent over a bounded channel
+/// to a background thread that stores them in the file, so that `set` only blocks when the buffer is full,
+/// and never waits for the file to grow. Values become visible in the dense file some time after `set`,
+/// use [`flush`](Self::flush) to wait for all of them to be stored.
+pub struct BufferedDenseCache {
+    sender: Option<SyncSender<Message>>,
+    writer: Option<JoinHandle<DenseFileCache>>,
+}
+
+impl BufferedDenseCache {
+    /// Start the writer thread, buffering up to `capacity` values
+    #[must_use]
+    pub fn new(cache: DenseFileCache, capacity: usize) -> Self {
+        let (sender, receiver) = sync_channel(capacity);
+        let writer = thread::spawn(move || {
+            run_writer(&cache, &receiver);
+            cache
+        });
+        Self {
+            sender: Some(sender),
+            writer: Some(writer),
+        }
+    }
+
+    /// Queue a value to be stored at index. Panics if the writer thread has panicked.
+    pub fn set(&self, index: usize, value: u64) {
+        if self.send(Message::Set(index, value)).is_err() {
+            panic!("BufferedDenseCache writer thread has stopped");
+        }
+    }
+
+    /// Wait for all queued values to be stored, and flush the dense file to disk
+    pub fn flush(&self) -> OsmNodeCacheResult<()> {
+        let (sender, receiver) = sync_channel(1);
+        self.send(Message::Flush(sender))?;
+        receiver.recv().map_err(|_| writer_stopped())?
+    }
+
+    /// Store all queued values, stop the writer thread, and return the dense file cache.
+    /// If the writer thread has panicked, e.g. because the file could not grow, the panic is resumed here.
+    pub fn join(mut self) -> OsmNodeCacheResult<DenseFileCache> {
+        self.sender = None;
+        let writer = self.writer.take().expect("writer is only taken by join");
+        let cache = writer.join().unwrap_or_else(|e| resume_unwind(e));
+        cache.flush()?;
+        Ok(cache)
+    }
+
+    fn send(&self, message: Message) -> io::Result<()> {
+        self.sender
+            .as_ref()
+            .expect("sender is only taken by join")
+            .send(message)
+            .map_err(|_| writer_stopped())
+    }
+}
+
+impl Drop for BufferedDenseCache {
+    /// Store all queued values before dropping. Use [`join`](Self::join) to handle errors.
+    fn drop(&mut self) {
+        self.sender = None;
+        if let Some(writer) = self.writer.take() {
+            let _ = writer.join();
+        }
+    }
+}
+
+fn writer_stopped() -> io::Error {
+    io::Error::new(
+        io::ErrorKind::BrokenPipe,
+        "BufferedDenseCache writer thread has stopped",
+    )
+}
+
+/// Store the received values in batches. The accessor is dropped while waiting for more values,
+/// so that it does not hold the read lock and block other writers from growing the file.
+fn run_writer(cache: &DenseFileCache, receiver: &Receiver<Message>) {
+    while let Ok(mut message) = receiver.recv() {
+        let mut accessor = cache.get_accessor();
+        loop {
+            match message {
+                Message::Set(index, value) => accessor.set(index, value),
+                Message::Flush(done) => {
+                    // Flushing takes the read lock again, which waits behind a writer growing the file
+                    // from another clone, so it must not be held by this thread while flushing
+                    drop(accessor);
+                    let _ = done.send(cache.flush());
+                    accessor = cache.get_accessor();
+                }
+            }
+            match receiver.try_recv() {
+                Ok(next) => message = next,
+                Err(_) => break,
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::path::PathBuf;
+    use std::thread;
+
+    use rayon::iter::{IntoParallelIterator, ParallelIterator};
+
+    use crate::*;
+
+    #[test]
+    fn buffered_test() {
+        let test_file = "./buffered_test.dat";
+        let _ = fs::remove_file(test_file);
+        {
+            let fc = DenseFileCacheOpts::new(PathBuf::from(test_file))
+                .page_size(800)
+                .open()
+                .unwrap();
+            let buffered = BufferedDenseCache::new(fc.clone(), 16);
+            buffered.set(5, 50);
+            buffered.flush().unwrap();
+            assert_eq!(fc.reader().get(5), 50);
+
+            (0..10_000_usize)
+                .into_par_iter()
+                .for_each(|i| buffered.set(i, i as u64 + 1));
+            let fc = buffered.join().unwrap();
+            let reader = fc.reader();
+            assert_eq!(reader.len(), 10_000);
+            assert!((0..10_000).all(|i| reader.get(i) == i as u64 + 1));
+        }
+        let _ = fs::remove_file(test_file);
+    }
+
+    #[test]
+    fn buffered_flush_while_growing() {
+        let test_file = "./buffered_flush_while_growing.dat";
+        let _ = fs::remove_file(test_file);
+        {
+            let fc = DenseFileCacheOpts::new(PathBuf::from(test_file))
+                .page_size(8)
+                .open()
+                .unwrap();
+            let grower = fc.clone();
+            let grower = thread::spawn(move || {
+                for i in 1..=200 {
+                    grower.get_accessor().set(i * 64, 1);
+                }
+            });
+            let buffered = BufferedDenseCache::new(fc.clone(), 16);
+            for i in 0..200 {
+                buffered.set(i, i as u64 + 1);
+                buffered.flush().unwrap();
+            }
+            grower.join().unwrap();
+            let fc = buffered.join().unwrap();
+            let reader = fc.reader();
+            assert!((0..200).all(|i| reader.get(i) == i as u64 + 1));
+            assert_eq!(reader.get(200 * 64), 1);
+        }
+        let _ = fs::remove_file(test_file);
+    }
+
+    #[test]
+    #[should_panic(expected = "read-only")]
+    fn buffered_writer_panic() {
+        let test_file = "./buffered_writer_panic.dat";
+        let _ = fs::remove_file(test_file);
+        let fc = DenseFileCacheOpts::new(PathBuf::from(test_file))
+            .page_size(8)
+            .open()
+            .unwrap()
+            .reopen_readonly()
+            .unwrap();
+        let _ = fs::remove_file(test_file);
+        let buffered = BufferedDenseCache::new(fc, 1);
+        buffered.set(1, 1);
+        let _ = buffered.join();
+    }
+}
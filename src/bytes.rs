@@ -0,0 +1,152 @@
+use std::sync::Arc;
+
+use crate::traits::{Cache, CacheStore};
+use crate::Endian;
+
+const ELEMENT_SIZE: usize = std::mem::size_of::<u64>();
+
+/// A read-only cache over the content of a dense cache file that is already in memory,
+/// e.g. downloaded into RAM in an environment without file or memory map access.
+/// Any trailing bytes that do not form a whole element are ignored.
+/// Every read is bounds-checked against the length of the buffer, so the data may come from
+/// an untrusted source: indexes beyond it are reported as missing by `try_get`, and panic in `get`,
+/// but never read past its end. See the `fuzz` directory for a fuzz target.
+#[derive(Clone)]
+pub struct BytesCache {
+    data: Arc<Vec<u8>>,
+    endian: Endian,
+}
+
+impl BytesCache {
+    #[must_use]
+    pub fn from_bytes(data: Arc<Vec<u8>>) -> Self {
+        Self {
+            data,
+            endian: Endian::Native,
+        }
+    }
+
+    /// Byte order of the values in the buffer, see [`crate::DenseFileCacheOpts::endianness`].
+    #[must_use]
+    pub fn endianness(mut self, endian: Endian) -> Self {
+        self.endian = endian;
+        self
+    }
+
+    /// Number of elements in the buffer
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.data.len() / ELEMENT_SIZE
+    }
+
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    fn load(&self, index: usize) -> Option<u64> {
+        let start = index.checked_mul(ELEMENT_SIZE)?;
+        let bytes = self.data.get(start..start.checked_add(ELEMENT_SIZE)?)?;
+        let mut value = [0; ELEMENT_SIZE];
+        value.copy_from_slice(bytes);
+        Some(u64::from_ne_bytes(value))
+    }
+}
+
+impl CacheStore for BytesCache {
+    fn get_accessor(&self) -> Box<dyn Cache + '_> {
+        Box::new(self.clone())
+    }
+}
+
+impl Cache for BytesCache {
+    fn get(&self, index: usize) -> u64 {
+        let value = self.load(index).unwrap_or_else(|| {
+            panic!("Index {index} exceeds cache size {}", self.len());
+        });
+        self.endian.decode(value)
+    }
+
+    /// Same as the dense file, a zero value is reported as missing.
+    fn try_get(&self, index: usize) -> Option<u64> {
+        match self.load(index)? {
+            0 => None,
+            v => Some(self.endian.decode(v)),
+        }
+    }
+
+    fn set(&mut self, _index: usize, _value: u64) {
+        panic!("BytesCache is read-only");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::path::PathBuf;
+    use std::sync::Arc;
+
+    use crate::*;
+
+    #[test]
+    fn bytes_cache() {
+        let test_file = "./bytes_cache.dat";
+        let _ = fs::remove_file(test_file);
+        {
+            let fc = DenseFileCacheOpts::new(PathBuf::from(test_file))
+                .page_size(80)
+                .endianness(Endian::Big)
+                .open()
+                .unwrap();
+            let mut cache = fc.get_accessor();
+            cache.set(1, 10);
+            cache.set(9, 90);
+            drop(cache);
+            fc.flush().unwrap();
+
+            let mut data = fs::read(test_file).unwrap();
+            data.extend_from_slice(&[1, 2, 3]);
+            let bc = BytesCache::from_bytes(Arc::new(data)).endianness(Endian::Big);
+            assert_eq!(bc.len(), 10);
+            let cache = bc.get_accessor();
+            assert_eq!(cache.get(1), 10);
+            assert_eq!(cache.get(9), 90);
+            assert_eq!(cache.get(2), 0);
+            assert_eq!(cache.try_get(2), None);
+            assert_eq!(cache.try_get(9), Some(90));
+            assert_eq!(cache.try_get(10), None);
+            assert_eq!(cache.try_get(usize::MAX), None);
+        }
+        let _ = fs::remove_file(test_file);
+    }
+
+    #[test]
+    fn bytes_cache_any_length() {
+        for len in 0..=20 {
+            let cache = BytesCache::from_bytes(Arc::new(vec![0xff; len]));
+            assert_eq!(cache.len(), len / 8);
+            for index in [0, 1, 2, 3, usize::MAX / 8, usize::MAX / 8 + 1, usize::MAX] {
+                let expected = if index < len / 8 {
+                    Some(u64::MAX)
+                } else {
+                    None
+                };
+                assert_eq!(cache.try_get(index), expected);
+            }
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "exceeds cache size")]
+    fn bytes_cache_out_of_range() {
+        let cache = BytesCache::from_bytes(Arc::new(vec![0; 16]));
+        let _ = cache.get(2);
+    }
+
+    #[test]
+    #[should_panic(expected = "read-only")]
+    fn bytes_cache_set() {
+        let mut cache = BytesCache::from_bytes(Arc::new(vec![0; 16]));
+        cache.set(0, 1);
+    }
+}
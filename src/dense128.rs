@@ -0,0 +1,218 @@
+use std::hint::spin_loop;
+use std::sync::atomic::{fence, AtomicU64, Ordering};
+use std::sync::Arc;
+
+use crate::dense_file::CacheWriter;
+use crate::traits::Cache;
+use crate::DenseFileCache;
+
+/// Number of sequence counters shared by all indexes. Each index uses the counter of `index % STRIPES`,
+/// so writers of different indexes rarely wait for each other.
+const STRIPES: usize = 64;
+
+/// A dense file cache of `u128` values, e.g. a node id together with a 64-bit hash.
+/// Each value is stored in two consecutive `u64` slots of the wrapped [`DenseFileCache`]
+/// (high half first), reusing its file and growing logic.
+///
+/// Only 64-bit atomics are needed: the two halves are guarded by a sequence lock, so a reader
+/// racing with a writer of the same index retries instead of getting the halves of two different values,
+/// and readers never block writers. The sequence counters are kept in memory and shared by clones,
+/// so values written by another process, or directly to the [`inner`](Self::inner) cache, are not covered.
+#[derive(Clone)]
+pub struct DenseFileCache128 {
+    inner: DenseFileCache,
+    seqs: Arc<[AtomicU64]>,
+}
+
+/// Accessor to get and set values of a [`DenseFileCache128`]
+pub struct Cache128Accessor<'a> {
+    inner: CacheWriter<'a>,
+    seqs: &'a [AtomicU64],
+}
+
+impl DenseFileCache128 {
+    #[must_use]
+    pub fn new(inner: DenseFileCache) -> Self {
+        let seqs = (0..STRIPES).map(|_| AtomicU64::new(0)).collect();
+        Self { inner, seqs }
+    }
+
+    /// Create an accessor, same as [`CacheStore::get_accessor`](crate::CacheStore::get_accessor)
+    #[must_use]
+    pub fn get_accessor(&self) -> Cache128Accessor<'_> {
+        Cache128Accessor {
+            inner: self.inner.accessor(),
+            seqs: &self.seqs,
+        }
+    }
+
+    /// Get the underlying cache of `u64` slots
+    #[must_use]
+    pub fn inner(&self) -> &DenseFileCache {
+        &self.inner
+    }
+}
+
+impl<'a> Cache128Accessor<'a> {
+    /// Get the value at index. Panics if the index exceeds cache size.
+    #[must_use]
+    pub fn get_u128(&self, index: usize) -> u128 {
+        let (high, low) = self.read(index, |cache, slot| cache.get(slot));
+        u128::from(high) << 64 | u128::from(low)
+    }
+
+    /// Get the value at index, or `None` if it is zero or beyond cache size.
+    #[must_use]
+    pub fn try_get_u128(&self, index: usize) -> Option<u128> {
+        let (high, low) = self.read(index, |cache, slot| cache.try_get(slot));
+        if high.is_none() && low.is_none() {
+            None
+        } else {
+            Some(u128::from(high.unwrap_or(0)) << 64 | u128::from(low.unwrap_or(0)))
+        }
+    }
+
+    /// Set the value at index, growing the file if needed.
+    pub fn set_u128(&mut self, index: usize, value: u128) {
+        // Grow before taking the sequence lock, as growing waits for the read locks of all accessors,
+        // including the ones of readers retrying on this lock
+        self.inner.reserve(index * 2 + 1, 0);
+        let seq = &self.seqs[index % STRIPES];
+        // An odd sequence marks a write in progress, so only one writer can move it from even to odd
+        let mut current = seq.load(Ordering::Relaxed);
+        loop {
+            if current & 1 == 0 {
+                match seq.compare_exchange_weak(
+                    current,
+                    current + 1,
+                    Ordering::Acquire,
+                    Ordering::Relaxed,
+                ) {
+                    Ok(_) => break,
+                    Err(actual) => current = actual,
+                }
+            } else {
+                spin_loop();
+                current = seq.load(Ordering::Relaxed);
+            }
+        }
+        // Readers that see any of the new halves must also see the odd sequence
+        fence(Ordering::Release);
+        self.inner
+            .store(index * 2, (value >> 64) as u64, Ordering::Relaxed);
+        self.inner
+            .store(index * 2 + 1, value as u64, Ordering::Relaxed);
+        seq.store(current + 2, Ordering::Release);
+    }
+
+    /// Read both halves of the value at index, retrying if they were being written meanwhile
+    fn read<T>(&self, index: usize, get: impl Fn(&CacheWriter<'a>, usize) -> T) -> (T, T) {
+        let seq = &self.seqs[index % STRIPES];
+        loop {
+            let before = seq.load(Ordering::Acquire);
+            if before & 1 == 0 {
+                let high = get(&self.inner, index * 2);
+                let low = get(&self.inner, index * 2 + 1);
+                // Order the reads of the halves before the second read of the sequence
+                fence(Ordering::Acquire);
+                if seq.load(Ordering::Relaxed) == before {
+                    return (high, low);
+                }
+            }
+            spin_loop();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::path::PathBuf;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::Arc;
+    use std::thread;
+
+    use crate::*;
+
+    #[test]
+    fn dense_file_128() {
+        let test_file = "./dense_file_128.dat";
+        let _ = fs::remove_file(test_file);
+        {
+            let fc = DenseFileCacheOpts::new(PathBuf::from(test_file))
+                .page_size(80)
+                .open()
+                .unwrap();
+            let cache = DenseFileCache128::new(fc);
+            let mut accessor = cache.get_accessor();
+            let value = (42_u128 << 64) | 0xdead_beef;
+            accessor.set_u128(3, value);
+            accessor.set_u128(4, 7);
+            accessor.set_u128(100, u128::MAX);
+            assert_eq!(accessor.get_u128(3), value);
+            assert_eq!(accessor.try_get_u128(4), Some(7));
+            assert_eq!(accessor.get_u128(100), u128::MAX);
+            assert_eq!(accessor.get_u128(5), 0);
+            assert_eq!(accessor.try_get_u128(5), None);
+            assert_eq!(accessor.try_get_u128(1000), None);
+            drop(accessor);
+            let reader = cache.inner().reader();
+            assert_eq!(reader.len(), 210);
+            assert_eq!(reader.get(6), 42);
+            assert_eq!(reader.get(7), 0xdead_beef);
+        }
+        let _ = fs::remove_file(test_file);
+    }
+
+    #[test]
+    fn dense_file_128_no_torn_reads() {
+        let test_file = "./dense_file_128_no_torn_reads.dat";
+        let _ = fs::remove_file(test_file);
+        {
+            let fc = DenseFileCacheOpts::new(PathBuf::from(test_file))
+                .page_size(800)
+                .open()
+                .unwrap();
+            let cache = DenseFileCache128::new(fc);
+            let done = Arc::new(AtomicBool::new(false));
+            let writers: Vec<_> = (0..2_u64)
+                .map(|w| {
+                    let cache = cache.clone();
+                    thread::spawn(move || {
+                        let mut accessor = cache.get_accessor();
+                        for i in 1..20_000_u64 {
+                            // Both halves always match, unless a read is torn
+                            let v = i * 2 + w;
+                            accessor
+                                .set_u128((i % 8) as usize, u128::from(v) << 64 | u128::from(v));
+                        }
+                    })
+                })
+                .collect();
+            let readers: Vec<_> = (0..2)
+                .map(|_| {
+                    let cache = cache.clone();
+                    let done = Arc::clone(&done);
+                    thread::spawn(move || {
+                        while !done.load(Ordering::Relaxed) {
+                            let accessor = cache.get_accessor();
+                            for index in 0..8 {
+                                if let Some(v) = accessor.try_get_u128(index) {
+                                    assert_eq!(v >> 64, v & u128::from(u64::MAX));
+                                }
+                            }
+                        }
+                    })
+                })
+                .collect();
+            for writer in writers {
+                writer.join().unwrap();
+            }
+            done.store(true, Ordering::Relaxed);
+            for reader in readers {
+                reader.join().unwrap();
+            }
+        }
+        let _ = fs::remove_file(test_file);
+    }
+}
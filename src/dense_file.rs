@@ -1,27 +1,147 @@
-use std::mem::{size_of, transmute};
-use std::path::PathBuf;
-use std::sync::atomic::{AtomicU64, Ordering};
-use std::sync::{Arc, Mutex, RwLock, RwLockReadGuard};
+#[cfg(unix)]
+use std::ffi::CString;
+use std::fmt;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufWriter, ErrorKind, IntoInnerError, Write};
+use std::mem::size_of;
+#[cfg(unix)]
+use std::mem::MaybeUninit;
+use std::ops::{Index, Range};
+#[cfg(unix)]
+use std::os::unix::ffi::OsStrExt;
+#[cfg(target_os = "linux")]
+use std::os::unix::io::AsRawFd;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, PoisonError, RwLock, RwLockReadGuard};
+use std::{process, slice};
 
 #[cfg(unix)]
 pub use memmap2::Advice;
-use memmap2::MmapMut;
+#[cfg(target_os = "linux")]
+use memmap2::RemapOptions;
+#[cfg(unix)]
+use memmap2::UncheckedAdvice;
+use memmap2::{MmapMut, MmapOptions, MmapRaw};
+use rayon::prelude::*;
+
+use crate::encoding::{pack_lat_lon, unpack_lat_lon};
+use crate::hasher::{murmur3_finish, murmur3_mix};
+use crate::traits::{format_bytes, format_count, open_cache_file, Cache, CacheStore};
+use crate::{HashMapCache, OsmNodeCacheError, OsmNodeCacheResult, OverlayCache};
+
+/// Approximate page size chosen by [`DenseFileCacheOpts::auto_page_size`]
+const AUTO_PAGE_SIZE: usize = 256 * 1024 * 1024; // 256 MB
+
+/// Block size used when the file system cannot be queried
+const DEFAULT_BLOCK_SIZE: usize = 4096;
+
+/// Byte order of the values stored in the cache file
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(rename_all = "lowercase")
+)]
+pub enum Endian {
+    /// Byte order of the current platform, which requires no conversion. This is the default.
+    Native,
+    Little,
+    Big,
+}
+
+/// How the file size is rounded up when the cache grows, see [`DenseFileCacheOpts::size_rounding`]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(rename_all = "snake_case")
+)]
+pub enum SizeRounding {
+    /// Round up to a multiple of the page size. This is the default.
+    Page,
+    /// Round up to a multiple of the page size, and then to the next power of two
+    PowerOfTwo,
+}
+
+impl Endian {
+    /// Convert a native value into its stored representation
+    #[inline]
+    pub(crate) fn encode(self, value: u64) -> u64 {
+        match self {
+            Endian::Native => value,
+            Endian::Little => value.to_le(),
+            Endian::Big => value.to_be(),
+        }
+    }
+
+    /// Name of the actual byte order, resolving `Native` to the one of the current platform
+    fn resolved_name(self) -> &'static str {
+        match self {
+            Endian::Native if cfg!(target_endian = "big") => "big",
+            Endian::Native | Endian::Little => "little",
+            Endian::Big => "big",
+        }
+    }
+
+    /// Convert a stored value into its native representation
+    #[inline]
+    pub(crate) fn decode(self, value: u64) -> u64 {
+        match self {
+            Endian::Native => value,
+            Endian::Little => u64::from_le(value),
+            Endian::Big => u64::from_be(value),
+        }
+    }
+}
+
+/// Name and version of the algorithm used by [`DenseFileCache::checksum`]:
+/// the `MurmurHash3` x64 mixing step over each stored 8-byte word of a chunk of 1M elements
+/// (read as little-endian) and its finalizer, followed by the same over the chunk hashes.
+pub const CHECKSUM_ALGORITHM: &str = "murmur3-words-chunked-1m-v2";
 
-use crate::traits::{open_cache_file, Cache, CacheStore};
-use crate::{OsmNodeCacheError, OsmNodeCacheResult};
+/// Number of elements hashed together by [`DenseFileCache::checksum`]
+const CHECKSUM_CHUNK: usize = 1024 * 1024;
 
 pub type OnSizeChange = fn(old_size: usize, new_size: usize) -> ();
+pub type OnGrowError = fn(error: &OsmNodeCacheError) -> ();
 
+/// With the `serde` feature the options can be (de)serialized, e.g. read from a config file.
+/// Missing fields get the same defaults as [`new`](Self::new), and the size change callback is not stored.
 #[derive(Clone)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(default = "DenseFileCacheOpts::serde_default")
+)]
 pub struct DenseFileCacheOpts {
     filename: Arc<PathBuf>,
     write: bool,
+    create: bool,
     autogrow: bool,
     init_size: usize,
     page_size: usize,
     #[cfg(unix)]
+    #[cfg_attr(feature = "serde", serde(with = "advice_serde"))]
     advice: Advice,
+    huge_pages: bool,
+    populate: bool,
+    lock_memory: bool,
+    flush_on_grow: bool,
+    sidecar_meta: bool,
+    flush_every_bytes: usize,
+    drop_cache_after_flush: bool,
+    seed_from: Option<PathBuf>,
+    endian: Endian,
+    size_rounding: SizeRounding,
+    #[cfg_attr(feature = "serde", serde(skip))]
     on_size_change: Option<OnSizeChange>,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    on_grow_error: Option<OnGrowError>,
+    /// Deletes the temporary cache file once the last clone of the options is dropped
+    #[cfg(feature = "tempfile")]
+    #[cfg_attr(feature = "serde", serde(skip))]
+    temp_path: Option<Arc<tempfile::TempPath>>,
 }
 
 impl DenseFileCacheOpts {
@@ -30,25 +150,77 @@ impl DenseFileCacheOpts {
         DenseFileCacheOpts {
             filename: Arc::new(filename),
             write: true,
+            create: true,
             autogrow: true,
             init_size: 1024 * 1024 * 1024, // 1 GB
             page_size: 1024 * 1024 * 1024, // 1 GB
             on_size_change: None,
+            on_grow_error: None,
             #[cfg(unix)]
             advice: Advice::Normal,
+            huge_pages: false,
+            populate: false,
+            lock_memory: false,
+            flush_on_grow: true,
+            sidecar_meta: false,
+            flush_every_bytes: 0,
+            drop_cache_after_flush: false,
+            seed_from: None,
+            endian: Endian::Native,
+            size_rounding: SizeRounding::Page,
+            #[cfg(feature = "tempfile")]
+            temp_path: None,
         }
     }
 
-    /// Allow data modification
+    #[cfg(feature = "serde")]
+    fn serde_default() -> Self {
+        Self::new(PathBuf::new())
+    }
+
+    /// Use a newly created temporary file for the cache. The file is deleted
+    /// once the cache and all clones of its options are dropped.
+    #[cfg(feature = "tempfile")]
+    pub fn temp() -> OsmNodeCacheResult<Self> {
+        let temp_path = tempfile::NamedTempFile::new()?.into_temp_path();
+        let mut opts = Self::new(temp_path.to_path_buf());
+        opts.temp_path = Some(Arc::new(temp_path));
+        Ok(opts)
+    }
+
+    /// Use a different cache file with the same settings, e.g. to open a set of sharded files.
+    /// A temporary file created by `temp` is not deleted on behalf of the new options.
     #[must_use]
-    pub fn write(mut self, write: bool) -> Self {
-        if !write {
-            todo!("Readonly cache is not supported yet")
+    pub fn with_filename(mut self, filename: PathBuf) -> Self {
+        self.filename = Arc::new(filename);
+        #[cfg(feature = "tempfile")]
+        {
+            self.temp_path = None;
         }
+        self
+    }
+
+    /// Path of the cache file
+    #[must_use]
+    pub fn filename(&self) -> &Path {
+        &self.filename
+    }
+
+    /// Allow data modification. A read-only cache maps the existing file without ever creating
+    /// or growing it, and panics on any attempt to set a value.
+    #[must_use]
+    pub fn write(mut self, write: bool) -> Self {
         self.write = write;
         self
     }
 
+    /// Create the cache file if it does not exist. Ignored for read-only files.
+    #[must_use]
+    pub fn create(mut self, create: bool) -> Self {
+        self.create = create;
+        self
+    }
+
     /// Set callback to report when cache size changes
     #[must_use]
     pub fn on_size_change(mut self, on_size_change: Option<OnSizeChange>) -> Self {
@@ -56,6 +228,15 @@ impl DenseFileCacheOpts {
         self
     }
 
+    /// Set callback to report why the file could not grow, e.g. because the disk is full.
+    /// It is called by `set` right before it panics, while the accessor still holds its lock,
+    /// so it must not access this cache. Use [`Cache::try_set`] to handle the error instead.
+    #[must_use]
+    pub fn on_grow_error(mut self, on_grow_error: Option<OnGrowError>) -> Self {
+        self.on_grow_error = on_grow_error;
+        self
+    }
+
     /// Automatically increase cache file size as needed. Ignored for read-only files.
     #[must_use]
     pub fn autogrow(mut self, autogrow: bool) -> Self {
@@ -80,63 +261,560 @@ impl DenseFileCacheOpts {
         self
     }
 
+    /// Pick the page size based on the block size of the file system holding the cache file:
+    /// 256 MB rounded up to a multiple of both the block size and the element size.
+    /// If the block size cannot be determined (e.g. on non-Unix systems), 4 KB blocks are assumed.
+    #[must_use]
+    pub fn auto_page_size(mut self) -> Self {
+        let block = fs_block_size(self.filename.as_ref())
+            .filter(|&b| b > 0)
+            .unwrap_or(DEFAULT_BLOCK_SIZE);
+        let align = if block % size_of::<u64>() == 0 {
+            block
+        } else {
+            block * size_of::<u64>()
+        };
+        self.page_size = (AUTO_PAGE_SIZE + align - 1) / align * align;
+        self
+    }
+
     #[must_use]
     pub fn advise(mut self, advice: Advice) -> Self {
         self.advice = advice;
         self
     }
 
+    /// Advise the kernel to back the memory map with transparent huge pages (`MADV_HUGEPAGE`),
+    /// reducing TLB misses on random access over a large file.
+    /// This is only a hint: it is ignored on non-Linux systems and on kernels without huge page support.
+    #[must_use]
+    pub fn huge_pages(mut self, huge_pages: bool) -> Self {
+        self.huge_pages = huge_pages;
+        self
+    }
+
+    /// Pre-fault the whole file into memory when mapping it (`MAP_POPULATE` on Linux, ignored elsewhere).
+    /// On Linux, pages added when growing the map in place are pre-faulted with `MADV_POPULATE_WRITE`,
+    /// which is skipped on kernels older than 5.14.
+    /// This makes opening and growing the cache slower, but avoids page faults on first access,
+    /// e.g. when the whole file is going to be read.
+    #[must_use]
+    pub fn populate(mut self, populate: bool) -> Self {
+        self.populate = populate;
+        self
+    }
+
+    /// Lock the whole memory map into RAM with `mlock`, so that its pages are never swapped out,
+    /// e.g. for consistent read latency. The locked size is limited by `RLIMIT_MEMLOCK` unless the
+    /// process has the `CAP_IPC_LOCK` capability. If the map cannot be locked, opening or re-mapping
+    /// the cache fails with [`OsmNodeCacheError::LockMemoryFailed`]. Ignored on non-Unix systems.
+    #[must_use]
+    pub fn lock_memory(mut self, lock_memory: bool) -> Self {
+        self.lock_memory = lock_memory;
+        self
+    }
+
+    /// Flush all changes to disk each time the file grows by re-creating the memory map (default).
+    /// On Linux this option has no effect for caches opened from a file, as their map is extended in place
+    /// with `mremap`, which never flushes. It only applies there to caches created with
+    /// [`DenseFileCache::from_mmap`], whose map is always re-created.
+    /// Skipping the flush makes growing much cheaper for large maps, and does not affect the data seen
+    /// by this or other processes, as the changes stay in the OS page cache. However, after a system crash
+    /// or power loss an unknown amount of changes is lost, so only disable it for caches that can be rebuilt,
+    /// or call [`DenseFileCache::flush`] at your own checkpoints, which is also needed on Linux.
+    #[must_use]
+    pub fn flush_on_grow(mut self, flush_on_grow: bool) -> Self {
+        self.flush_on_grow = flush_on_grow;
+        self
+    }
+
+    /// Flush the changes to disk each time another `bytes` have been written by `set`, bounding the amount
+    /// of data lost on a system crash without a background thread (disabled with 0, the default).
+    /// The count is shared by all accessors and clones of the cache, so the flush is done by whichever
+    /// `set` crosses the threshold, which then panics if the flush fails. Each value counts as 8 bytes,
+    /// also when overwriting a slot, and values written any other way are not counted.
+    /// Counting adds an atomic increment of a shared counter to each `set`.
+    #[must_use]
+    pub fn flush_every_bytes(mut self, bytes: usize) -> Self {
+        self.flush_every_bytes = bytes;
+        self
+    }
+
+    /// After each flush, evict the flushed pages from memory with `madvise(MADV_DONTNEED)`,
+    /// and on Linux also from the OS page cache with `posix_fadvise(POSIX_FADV_DONTNEED)`,
+    /// e.g. when building a cache that is written once, so that it does not push other data out of RAM.
+    /// This applies to the whole map, as written ranges are not tracked, so any value read later
+    /// has to be loaded from disk again, which makes flushing and subsequent reads much slower.
+    /// Values stored while flushing are not lost, and are only written with the next flush.
+    /// Combine with [`flush_every_bytes`](Self::flush_every_bytes) to bound the memory used while writing.
+    /// Does nothing on non-Unix platforms.
+    #[must_use]
+    pub fn drop_cache_after_flush(mut self, drop_cache_after_flush: bool) -> Self {
+        self.drop_cache_after_flush = drop_cache_after_flush;
+        self
+    }
+
+    /// Keep the cache metadata in a `<filename>.meta` JSON file next to the cache file:
+    /// the largest index with a value, the element size, and the byte order.
+    /// The file is written by [`DenseFileCache::flush`] and when the last clone of a writable cache
+    /// is dropped, both of which then scan the whole cache for the largest index.
+    /// When opening, an existing metadata file is validated against these options,
+    /// and the cache file is grown to hold the stored largest index.
+    #[must_use]
+    pub fn sidecar_meta(mut self, sidecar_meta: bool) -> Self {
+        self.sidecar_meta = sidecar_meta;
+        self
+    }
+
+    /// If the cache file does not exist, start it as a copy of the `seed` file, e.g. a snapshot of a base cache.
+    /// The copy uses `copy_file_range` on Linux, which shares the data blocks on file systems that support it.
+    /// It is made under a temporary name and then hard-linked into place, so that concurrent openers
+    /// never see a partial copy, and an existing cache file is never overwritten.
+    /// Ignored for read-only files and when [`create`](Self::create) is disabled.
+    #[must_use]
+    pub fn seed_from(mut self, seed: PathBuf) -> Self {
+        self.seed_from = Some(seed);
+        self
+    }
+
+    /// Path of the metadata file, see [`sidecar_meta`](Self::sidecar_meta)
+    fn meta_filename(&self) -> PathBuf {
+        let mut name = self.filename.as_os_str().to_owned();
+        name.push(".meta");
+        PathBuf::from(name)
+    }
+
+    /// Read and validate the metadata file if it exists, returning the stored largest index
+    fn read_meta(&self) -> OsmNodeCacheResult<Option<usize>> {
+        let filename = self.meta_filename();
+        let data = match fs::read(&filename) {
+            Ok(data) => data,
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e.into()),
+        };
+        let meta: serde_json::Value = serde_json::from_slice(&data)?;
+        let invalid = |msg: String| {
+            OsmNodeCacheError::InvalidCacheFile(
+                filename.clone(),
+                std::io::Error::new(ErrorKind::InvalidData, msg),
+            )
+        };
+        let element_size = meta["element_size"].as_u64();
+        if element_size != Some(size_of::<u64>() as u64) {
+            return Err(invalid(format!(
+                "element size {element_size:?} does not match {}",
+                size_of::<u64>()
+            )));
+        }
+        let endian = meta["endian"].as_str();
+        if endian != Some(self.endian.resolved_name()) {
+            return Err(invalid(format!(
+                "byte order {endian:?} does not match {}",
+                self.endian.resolved_name()
+            )));
+        }
+        Ok(meta["max_index"].as_u64().map(|v| v as usize))
+    }
+
+    fn write_meta(&self, max_index: Option<usize>) -> OsmNodeCacheResult<()> {
+        let meta = serde_json::json!({
+            "max_index": max_index,
+            "element_size": size_of::<u64>(),
+            "endian": self.endian.resolved_name(),
+        });
+        fs::write(self.meta_filename(), serde_json::to_vec(&meta)?)?;
+        Ok(())
+    }
+
+    /// How the file size is rounded up when the cache grows to hold an index, e.g. [`SizeRounding::PowerOfTwo`]
+    /// to match the extent allocation of the file system. Sizes are still multiples of `page_size`
+    /// if it is a power of two itself, otherwise only the sizes of up to one page are.
+    /// Each step at least doubles the file, so later steps grow it much more than `page_size`.
+    #[must_use]
+    pub fn size_rounding(mut self, size_rounding: SizeRounding) -> Self {
+        self.size_rounding = size_rounding;
+        self
+    }
+
+    /// Byte order of the values in the cache file. Use an explicit byte order for files
+    /// shared between platforms. Non-native byte order requires a byte swap on each access.
+    #[must_use]
+    pub fn endianness(mut self, endian: Endian) -> Self {
+        self.endian = endian;
+        self
+    }
+
     /// Open and initialize cache file.
     pub fn open(self) -> OsmNodeCacheResult<DenseFileCache> {
         DenseFileCache::new_opt(self)
     }
 }
 
+/// Store the memory advice as a lowercase snake case string, e.g. `"will_need"`.
+/// Only the advice values available on all Unix systems are supported.
+#[cfg(all(unix, feature = "serde"))]
+mod advice_serde {
+    use serde::de::Error as _;
+    use serde::ser::Error as _;
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    use super::Advice;
+
+    const NAMES: [(&str, Advice); 4] = [
+        ("normal", Advice::Normal),
+        ("random", Advice::Random),
+        ("sequential", Advice::Sequential),
+        ("will_need", Advice::WillNeed),
+    ];
+
+    pub fn serialize<S: Serializer>(advice: &Advice, serializer: S) -> Result<S::Ok, S::Error> {
+        match NAMES.iter().find(|(_, v)| v == advice) {
+            Some((name, _)) => serializer.serialize_str(name),
+            None => Err(S::Error::custom(format!("unsupported advice {advice:?}"))),
+        }
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Advice, D::Error> {
+        let name = String::deserialize(deserializer)?;
+        match NAMES.iter().find(|(n, _)| *n == name) {
+            Some((_, advice)) => Ok(*advice),
+            None => Err(D::Error::unknown_variant(
+                &name,
+                &["normal", "random", "sequential", "will_need"],
+            )),
+        }
+    }
+}
+
+/// Query the block size of the file system containing the given file
+#[cfg(unix)]
+fn fs_block_size(filename: &Path) -> Option<usize> {
+    let dir = match filename.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+    let dir = CString::new(dir.as_os_str().as_bytes()).ok()?;
+    let mut stat = MaybeUninit::<libc::statvfs>::uninit();
+    if unsafe { libc::statvfs(dir.as_ptr(), stat.as_mut_ptr()) } == 0 {
+        Some(unsafe { stat.assume_init() }.f_bsize as usize)
+    } else {
+        None
+    }
+}
+
+#[cfg(not(unix))]
+fn fs_block_size(_filename: &Path) -> Option<usize> {
+    None
+}
+
+/// Open the cache file for reading, and for writing unless the cache is read-only.
+/// Also reports if the file did not exist and has been created by this call.
+fn open_file(opts: &DenseFileCacheOpts, create: bool) -> OsmNodeCacheResult<(File, bool)> {
+    let filename = opts.filename.as_ref();
+    let to_err = |e| OsmNodeCacheError::InvalidCacheFile(filename.to_path_buf(), e);
+    if !opts.write {
+        return Ok((File::open(filename).map_err(to_err)?, false));
+    }
+    if create {
+        // Creating the file exclusively tells apart a new file from an existing one without a race
+        let new_file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create_new(true)
+            .open(filename);
+        match new_file {
+            Ok(file) => return Ok((file, true)),
+            Err(e) if e.kind() == ErrorKind::AlreadyExists => {}
+            Err(e) => return Err(to_err(e)),
+        }
+    }
+    Ok((open_cache_file(filename, false)?, false))
+}
+
+/// Copy the seed file into place if the cache file does not exist, see [`DenseFileCacheOpts::seed_from`].
+/// Returns whether the cache file has been created.
+fn seed_file(opts: &DenseFileCacheOpts) -> OsmNodeCacheResult<bool> {
+    static SEED_COUNTER: AtomicUsize = AtomicUsize::new(0);
+    let seed = match &opts.seed_from {
+        Some(seed) if opts.write && opts.create => seed,
+        _ => return Ok(false),
+    };
+    let filename = opts.filename.as_ref();
+    if filename.exists() {
+        return Ok(false);
+    }
+    let name = filename.file_name().unwrap_or_default().to_string_lossy();
+    let temp = filename.with_file_name(format!(
+        ".{name}.{}.{}.seed",
+        process::id(),
+        SEED_COUNTER.fetch_add(1, Ordering::Relaxed)
+    ));
+    let result = fs::copy(seed, &temp).and_then(|_| fs::hard_link(&temp, filename));
+    let _ = fs::remove_file(&temp);
+    match result {
+        Ok(()) => Ok(true),
+        // Another opener has created the file in the meantime
+        Err(e) if e.kind() == ErrorKind::AlreadyExists => Ok(false),
+        Err(e) => Err(OsmNodeCacheError::InvalidCacheFile(seed.clone(), e)),
+    }
+}
+
 /// Increase the size of the file if needed, and create a memory map from it
-fn resize_and_memmap(index: usize, opts: &DenseFileCacheOpts) -> OsmNodeCacheResult<MmapMut> {
-    if opts.page_size % size_of::<usize>() != 0 {
+fn resize_and_memmap(index: usize, opts: &DenseFileCacheOpts) -> OsmNodeCacheResult<MmapRaw> {
+    let (file, size, _) = resize_file(index, opts)?;
+    map_file(&file, size, opts)
+}
+
+/// Check that the page size can be used for [`DenseFileCacheOpts::page_size`],
+/// i.e. it is a non-zero multiple of the element size, e.g. to validate a config before opening the cache.
+pub fn validate_page_size(page_size: usize) -> OsmNodeCacheResult<()> {
+    if page_size == 0 || page_size % size_of::<usize>() != 0 {
         return Err(OsmNodeCacheError::InvalidPageSize {
-            page_size: opts.page_size,
+            page_size,
             element_size: size_of::<usize>(),
         });
     }
+    Ok(())
+}
+
+/// Increase the size of the file if needed to hold the index, returning the file, its size,
+/// and whether it has just been created. Read-only files are never resized.
+fn resize_file(index: usize, opts: &DenseFileCacheOpts) -> OsmNodeCacheResult<(File, u64, bool)> {
+    validate_page_size(opts.page_size)?;
 
-    let file = open_cache_file(opts.filename.as_ref())?;
+    let (file, created) = open_file(opts, opts.create)?;
     let old_size = file.metadata().unwrap().len();
+    if !opts.write {
+        return Ok((file, old_size, created));
+    }
 
     let capacity = (index + 1) * size_of::<usize>();
     let pages = capacity / opts.page_size + (if capacity % opts.page_size == 0 { 0 } else { 1 });
-    let new_size = (pages * opts.page_size) as u64;
+    let new_size = match opts.size_rounding {
+        SizeRounding::Page => pages * opts.page_size,
+        SizeRounding::PowerOfTwo => (pages * opts.page_size)
+            .checked_next_power_of_two()
+            .ok_or_else(|| {
+                io::Error::new(
+                    ErrorKind::InvalidInput,
+                    format!("Cache size for index {index} exceeds the largest power of two"),
+                )
+            })?,
+    } as u64;
     if old_size < new_size {
         if let Some(value) = opts.on_size_change {
             value(old_size as usize, new_size as usize);
         }
-        file.set_len(new_size)?;
+        file.set_len(new_size)
+            .map_err(|e| resize_error(e, opts, new_size))?;
+    }
+    Ok((file, old_size.max(new_size), created))
+}
+
+/// Report running out of disk space or quota as [`OsmNodeCacheError::DiskFull`], so that it can be handled
+fn resize_error(error: io::Error, opts: &DenseFileCacheOpts, size: u64) -> OsmNodeCacheError {
+    #[cfg(unix)]
+    let disk_full = matches!(error.raw_os_error(), Some(libc::ENOSPC | libc::EDQUOT));
+    #[cfg(not(unix))]
+    let disk_full = false;
+    if disk_full || error.kind() == ErrorKind::WriteZero {
+        OsmNodeCacheError::DiskFull {
+            path: opts.filename.to_path_buf(),
+            requested_size: size,
+            source: error,
+        }
+    } else {
+        error.into()
+    }
+}
+
+/// Grow the file and the memory map to hold the index. On Linux, the existing map is
+/// extended with `mremap` instead of flushing and re-creating it, unless the map has not been
+/// created from the cache file, see [`DenseFileCache::from_mmap`].
+/// A locked map stays locked, including the added pages.
+/// The caller must hold the write lock, so that no references into the map exist.
+#[cfg(target_os = "linux")]
+fn grow_memmap(
+    mmap: &mut MmapRaw,
+    index: usize,
+    opts: &DenseFileCacheOpts,
+    file_map: bool,
+) -> OsmNodeCacheResult<()> {
+    if !file_map {
+        return recreate_memmap(mmap, index, opts);
+    }
+    let old_len = mmap.len();
+    let (_file, size, _) = resize_file(index, opts)?;
+    // Safety: the map has been created from the file, which has just been resized
+    // to at least the new map size
+    unsafe { mmap.remap(size as usize, RemapOptions::new().may_move(true)) }.map_err(|e| {
+        OsmNodeCacheError::MmapFailed {
+            filename: opts.filename.to_path_buf(),
+            size,
+            source: e,
+        }
+    })?;
+    if opts.huge_pages {
+        // Only a hint, the kernel may not support transparent huge pages
+        let _ = mmap.advise(Advice::HugePage);
+    }
+    if opts.populate && mmap.len() > old_len {
+        // `mremap` does not pre-fault the added pages like `MAP_POPULATE` does.
+        // Only a hint, older kernels do not support it.
+        let _ = mmap.advise_range(Advice::PopulateWrite, old_len, mmap.len() - old_len);
+    }
+    Ok(())
+}
+
+/// Grow the file and the memory map to hold the index by re-creating the map.
+#[cfg(not(target_os = "linux"))]
+fn grow_memmap(
+    mmap: &mut MmapRaw,
+    index: usize,
+    opts: &DenseFileCacheOpts,
+    _file_map: bool,
+) -> OsmNodeCacheResult<()> {
+    recreate_memmap(mmap, index, opts)
+}
+
+/// Grow the file and re-create the memory map from it, flushing the old map first
+/// if [`DenseFileCacheOpts::flush_on_grow`] is set.
+fn recreate_memmap(
+    mmap: &mut MmapRaw,
+    index: usize,
+    opts: &DenseFileCacheOpts,
+) -> OsmNodeCacheResult<()> {
+    if opts.flush_on_grow {
+        mmap.flush()?;
+    }
+    *mmap = resize_and_memmap(index, opts)?;
+    Ok(())
+}
+
+/// Create a memory map of the whole file of the given size, applying the mapping options
+fn map_file(file: &File, size: u64, opts: &DenseFileCacheOpts) -> OsmNodeCacheResult<MmapRaw> {
+    let mut mmap_opts = MmapOptions::new();
+    if opts.populate {
+        mmap_opts.populate();
+    }
+    let mmap = if opts.write {
+        mmap_opts.map_raw(file)
+    } else {
+        mmap_opts.map_raw_read_only(file)
+    };
+    let mmap = mmap.map_err(|e| OsmNodeCacheError::MmapFailed {
+        filename: opts.filename.to_path_buf(),
+        size,
+        source: e,
+    })?;
+    #[cfg(target_os = "linux")]
+    if opts.huge_pages {
+        // Only a hint, the kernel may not support transparent huge pages
+        let _ = mmap.advise(Advice::HugePage);
+    }
+    #[cfg(unix)]
+    if opts.lock_memory {
+        mmap.lock()
+            .map_err(|e| OsmNodeCacheError::LockMemoryFailed {
+                filename: opts.filename.to_path_buf(),
+                size,
+                source: e,
+            })?;
     }
-    Ok(unsafe { MmapMut::map_mut(&file)? })
+    Ok(mmap)
+}
+
+fn lock_and_link(
+    memmap: &RwLock<MmapRaw>,
+) -> OsmNodeCacheResult<(Option<RwLockReadGuard<'_, MmapRaw>>, &[AtomicU64])> {
+    Ok(link(memmap.read()?))
 }
 
-fn lock_and_link(memmap: &RwLock<MmapMut>) -> (Option<RwLockReadGuard<MmapMut>>, &[AtomicU64]) {
-    let mm = memmap.read().unwrap();
-    // ideally this should be as_mut(), but mut is not multithreaded
-    let data_as_u8: &[u8] = mm.as_ref();
-    // Major hack -- the array actually contains [u8], but AtomicU64 appear to work and simplify things
-    let raw_data: &[AtomicU64] = unsafe { transmute(data_as_u8) };
+/// Get the values of the map while holding its read lock
+fn link(mm: RwLockReadGuard<'_, MmapRaw>) -> (Option<RwLockReadGuard<'_, MmapRaw>>, &[AtomicU64]) {
+    // Major hack -- the array actually contains [u8], but AtomicU64 appear to work and simplify things.
+    // The memory map is page-aligned, so it is always properly aligned for AtomicU64.
+    // A read-only map must never be written to, which is checked by all writing methods.
+    let raw_data: &[AtomicU64] =
+        unsafe { slice::from_raw_parts(mm.as_ptr().cast(), mm.len() / size_of::<AtomicU64>()) };
 
     (Some(mm), raw_data)
 }
 
+/// A view of the values of a [`CacheReader`], borrowing the reader so that its read lock is held.
+/// Unlike the reader, a view is `Copy` and `Send`, e.g. to give each rayon task its own copy
+/// with `for_each_with`. It has no lock of its own, so getting one costs nothing.
+#[derive(Clone, Copy)]
+pub struct CacheView<'a> {
+    raw_data: &'a [AtomicU64],
+    endian: Endian,
+}
+
 #[derive(Clone)]
 pub struct DenseFileCache {
     opts: DenseFileCacheOpts,
-    memmap: Arc<RwLock<MmapMut>>,
+    memmap: Arc<RwLock<MmapRaw>>,
     mutex: Arc<Mutex<()>>,
+    created: bool,
+    /// Number of times the file has been grown by any clone of this cache
+    grow_count: Arc<AtomicU64>,
+    /// Whether any clone of this cache may have changed the data since the last flush
+    dirty: Arc<AtomicBool>,
+    /// Bytes stored by `set` with any clone of this cache, see [`DenseFileCacheOpts::flush_every_bytes`]
+    bytes_written: Arc<AtomicU64>,
+    /// Whether the map has been created from the cache file, so that it can be extended in place,
+    /// instead of a map passed to [`from_mmap`](Self::from_mmap)
+    file_map: bool,
+}
+
+/// A snapshot of the [`DenseFileCache`] state, see [`DenseFileCache::stats`]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct DenseFileStats {
+    /// Size of the memory map in bytes, i.e. the file size when it was last mapped
+    pub file_size: u64,
+    /// Number of elements the cache can hold without growing
+    pub capacity_elements: usize,
+    /// Number of times the file has been grown while setting values through this cache or its clones
+    pub grow_count: u64,
+    /// Largest index holding a non-zero value, see [`DenseFileCache::find_max_index`]
+    pub max_index: Option<usize>,
+    /// Memory advice configured with [`DenseFileCacheOpts::advise`] and applied when opening the cache.
+    /// Later calls to [`DenseFileCache::advise`] or [`DenseFileCache::advise_range`] are not reflected,
+    /// and the advice is lost when the cache grows or is re-mapped.
+    #[cfg(unix)]
+    pub open_advice: Advice,
 }
 
-struct CacheWriter<'a> {
+/// Accessor to get and set values of a [`DenseFileCache`], see [`DenseFileCache::accessor`].
+/// The accessor holds a read lock while it is not growing the file, same as [`CacheReader`],
+/// so drop it before another accessor of this cache waits for it, e.g. on the same thread.
+pub struct CacheWriter<'a> {
     parent: &'a DenseFileCache,
-    mm_setter: Option<RwLockReadGuard<'a, MmapMut>>,
+    mm_setter: Option<RwLockReadGuard<'a, MmapRaw>>,
+    raw_data: &'a [AtomicU64],
+}
+
+/// Read-only view of a [`DenseFileCache`].
+/// The reader holds a read lock for its whole lifetime, preventing the file from growing,
+/// so keep it short-lived if other threads may need to write beyond the current file size.
+pub struct CacheReader<'a> {
+    _mm_guard: Option<RwLockReadGuard<'a, MmapRaw>>,
     raw_data: &'a [AtomicU64],
+    endian: Endian,
+}
+
+/// A short summary for status output, e.g. `DenseFileCache(file=planet.dat, 7.5B elements, 60GB)`
+impl fmt::Display for DenseFileCache {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let elements = self.len_elements() as u64;
+        write!(
+            f,
+            "DenseFileCache(file={}, {} elements, {})",
+            self.opts.filename.display(),
+            format_count(elements),
+            format_bytes(elements * size_of::<u64>() as u64)
+        )
+    }
 }
 
 impl DenseFileCache {
@@ -145,119 +823,802 @@ impl DenseFileCache {
         DenseFileCacheOpts::new(filename).open()
     }
 
+    /// Open an existing cache file, failing if it does not exist
+    pub fn open_existing(filename: PathBuf) -> OsmNodeCacheResult<Self> {
+        DenseFileCacheOpts::new(filename).create(false).open()
+    }
+
     #[cfg(unix)]
     pub fn advise(&self, advice: Advice) -> OsmNodeCacheResult<()> {
-        self.memmap.read().unwrap().advise(advice)?;
+        self.memmap.read()?.advise(advice)?;
         Ok(())
     }
 
-    fn new_opt(opts: DenseFileCacheOpts) -> OsmNodeCacheResult<Self> {
-        let mmap = resize_and_memmap(0, &opts)?;
-        let cache = Self {
-            opts,
-            memmap: Arc::new(RwLock::new(mmap)),
-            mutex: Arc::new(Mutex::new(())),
-        };
-        #[cfg(unix)]
-        if cache.opts.advice != Advice::Normal {
-            cache.advise(cache.opts.advice)?;
+    /// Advise the kernel how slots `start_index..end_index` will be accessed, e.g. sequential reads
+    /// of one part of the file and random reads of another. The range is clamped to the current file size.
+    /// Same as [`advise`](Self::advise), the advice is lost when the cache grows or is re-mapped.
+    #[cfg(unix)]
+    pub fn advise_range(
+        &self,
+        start_index: usize,
+        end_index: usize,
+        advice: Advice,
+    ) -> OsmNodeCacheResult<()> {
+        let mm = self.memmap.read()?;
+        let len = mm.len() / size_of::<u64>();
+        let end = end_index.min(len);
+        if start_index < end {
+            let elem = size_of::<u64>();
+            mm.advise_range(advice, start_index * elem, (end - start_index) * elem)?;
         }
-        Ok(cache)
+        Ok(())
     }
-}
 
-impl CacheStore for DenseFileCache {
-    fn get_accessor(&self) -> Box<dyn Cache + '_> {
-        let (mm_setter, raw_data) = lock_and_link(&self.memmap);
-        Box::new(CacheWriter {
-            parent: self,
-            mm_setter,
-            raw_data,
-        })
+    /// Flush all outstanding changes to disk, and write the metadata file
+    /// if [`sidecar_meta`](DenseFileCacheOpts::sidecar_meta) is enabled.
+    /// Does nothing if no values have been set since the last flush, see [`is_dirty`](Self::is_dirty).
+    pub fn flush(&self) -> OsmNodeCacheResult<()> {
+        let flushed = self.flush_map(&*self.memmap.read()?)?;
+        if !flushed {
+            return Ok(());
+        }
+        if self.opts.sidecar_meta && self.opts.write {
+            self.opts.write_meta(self.find_max_index())?;
+        }
+        Ok(())
     }
-}
 
-impl<'a> CacheWriter<'a> {
-    fn len(&self) -> usize {
-        // hack: len() is in bytes, not u64s
-        self.raw_data.len() / size_of::<usize>()
+    /// Flush all changes and release this cache, returning any error instead of ignoring it like `drop`,
+    /// e.g. before uploading the file. The metadata file is written here if this is the last clone,
+    /// see [`sidecar_meta`](DenseFileCacheOpts::sidecar_meta). The memory map is only unmapped once
+    /// the last clone is dropped or closed, so any remaining clones keep using it as before.
+    pub fn close(mut self) -> OsmNodeCacheResult<()> {
+        let flushed = self.flush_map(&*self.memmap.read()?)?;
+        let last = Arc::strong_count(&self.memmap) == 1;
+        if self.opts.sidecar_meta && self.opts.write && (flushed || last) {
+            self.opts.write_meta(self.find_max_index())?;
+        }
+        // The metadata is up to date, so dropping this clone does not need to write it again
+        self.opts.sidecar_meta = false;
+        Ok(())
     }
-}
 
-impl<'a> Cache for CacheWriter<'a> {
-    fn get(&self, index: usize) -> u64 {
-        assert!(
-            index < self.len(),
-            "Index {index} exceeds cache size {}",
-            self.len()
-        );
-        self.raw_data[index].load(Ordering::Relaxed)
+    /// Flush the map if it is dirty, returning whether it was
+    fn flush_map(&self, mm: &MmapRaw) -> OsmNodeCacheResult<bool> {
+        // Clearing the flag before the flush keeps it set by any value stored while flushing
+        if !self.dirty.swap(false, Ordering::AcqRel) {
+            return Ok(false);
+        }
+        if let Err(e) = mm.flush() {
+            self.mark_dirty();
+            return Err(e.into());
+        }
+        #[cfg(unix)]
+        if self.opts.drop_cache_after_flush {
+            self.drop_page_cache(mm)?;
+        }
+        Ok(true)
     }
 
-    /// Set value at index position in the open memory map.
-    /// The existence of this object implies it already holds a read lock
-    /// If needed, this fn will release the read lock, get a write lock to grow the file,
-    /// and re-acquire the read lock.
-    /// Note that `RwLock` is a misnomer here:
-    ///    "read" lock means we can write to memmap (OK in parallel)
-    ///    "write" lock means we can destroy memmap, grow file, and re-create memmap (exclusive)
-    /// It would be prohibitively expensive to acquire a read lock on each call.
-    fn set(&mut self, index: usize, value: u64) {
-        if index >= self.len() {
-            // Ensure we save everything and drop the lock.
-            // Growing file size can only happen inside the write lock.
-            // We must get a separate mutex lock before the write lock because otherwise
-            // one thread could get write lock, grow, and get the read lock, while some
-            // other thread could be stuck waiting for the write lock even though the file
-            // has already been grown.
-            self.mm_setter = None;
-            {
-                let _pre_write_lock = self.parent.mutex.lock().unwrap();
-                if index >= self.len() {
-                    let p = self.parent;
-                    let mut write_lock = p.memmap.write().unwrap();
-                    write_lock.flush().unwrap();
-                    *write_lock = resize_and_memmap(index, &p.opts).unwrap();
-                }
+    /// Evict the pages of the map from memory, see [`DenseFileCacheOpts::drop_cache_after_flush`]
+    #[cfg(unix)]
+    fn drop_page_cache(&self, mm: &MmapRaw) -> OsmNodeCacheResult<()> {
+        // Safety: the map is shared with the file, so dropping its pages does not discard any data,
+        // including values stored concurrently, and existing references read the file content again
+        unsafe { mm.unchecked_advise(UncheckedAdvice::DontNeed) }?;
+        #[cfg(target_os = "linux")]
+        {
+            let (file, _) = open_file(&self.opts, false)?;
+            // Only clean pages are dropped, so this is a hint that cannot lose data
+            let res =
+                unsafe { libc::posix_fadvise(file.as_raw_fd(), 0, 0, libc::POSIX_FADV_DONTNEED) };
+            if res != 0 {
+                return Err(io::Error::from_raw_os_error(res).into());
             }
+        }
+        Ok(())
+    }
+
+    /// Whether any values may have been set since the last [`flush`](Self::flush).
+    /// The flag is set after each `set`, [`fill`](Self::fill), and [`zero_range`](Self::zero_range),
+    /// but not by stores through [`CacheReader::get_ref`] or raw pointers, so call
+    /// [`mark_dirty`](Self::mark_dirty) after those. It is only checked with relaxed atomics,
+    /// so a value set concurrently with a flush may be included in it, or left for the next one.
+    #[must_use]
+    pub fn is_dirty(&self) -> bool {
+        self.dirty.load(Ordering::Relaxed)
+    }
 
-            let (mm_setter, raw_data) = lock_and_link(&self.parent.memmap);
-            self.mm_setter = mm_setter;
-            self.raw_data = raw_data;
+    /// Make the next [`flush`](Self::flush) write the data to disk, e.g. after modifying it directly.
+    pub fn mark_dirty(&self) {
+        // Checking first avoids writing to the shared cache line on every `set`
+        if !self.dirty.load(Ordering::Relaxed) {
+            self.dirty.store(true, Ordering::Release);
         }
-        self.raw_data[index].store(value, Ordering::Relaxed);
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use std::fs;
-    use std::path::PathBuf;
+    /// Number of elements the cache can hold without growing.
+    /// The size is still reported if the lock was poisoned, as it is never left half-updated.
+    #[must_use]
+    pub fn len_elements(&self) -> usize {
+        let mm = self.memmap.read().unwrap_or_else(PoisonError::into_inner);
+        mm.len() / size_of::<u64>()
+    }
 
-    use rayon::iter::{ParallelBridge, ParallelIterator};
+    /// Re-create the memory map using the current size of the file,
+    /// picking up any growth made by another process sharing the same file.
+    pub fn remap(&self) -> OsmNodeCacheResult<()> {
+        let _pre_write_lock = self.mutex.lock()?;
+        let mut write_lock = self.memmap.write()?;
+        write_lock.flush()?;
+        let (file, _) = open_file(&self.opts, false)?;
+        let size = file.metadata()?.len();
+        *write_lock = map_file(&file, size, &self.opts)?;
+        Ok(())
+    }
 
-    use crate::traits::tests::get_random_items;
-    use crate::*;
+    /// Open the same file again with its own memory map and locks, e.g. for a separate thread pool
+    /// that should not wait for the locks of this cache. Unlike [`Clone`], which shares the map,
+    /// growing one of them does not resize the map of the other one, which keeps its size until
+    /// it grows itself or calls [`remap`](Self::remap). Both maps share the same file pages,
+    /// so values set through one of them are visible in the other one within the common size.
+    pub fn try_clone(&self) -> OsmNodeCacheResult<Self> {
+        let (file, _) = open_file(&self.opts, false)?;
+        let size = file.metadata()?.len();
+        let mmap = map_file(&file, size, &self.opts)?;
+        let mut cache = Self::from_raw(mmap, self.opts.clone());
+        cache.created = self.created;
+        #[cfg(unix)]
+        if cache.opts.advice != Advice::Normal {
+            cache.advise(cache.opts.advice)?;
+        }
+        Ok(cache)
+    }
 
-    #[test]
-    fn dense_file() {
-        let test_file = "./dense_file_test.dat";
-        let _ = fs::remove_file(test_file);
-        {
-            let fc = DenseFileCacheOpts::new(PathBuf::from(test_file))
-                .page_size(8)
-                .open()
-                .unwrap();
-            let threads = 10;
-            let items = 100000;
-            (0_usize..threads)
-                .par_bridge()
-                .for_each_with(fc.clone(), |fc, _thread_id| {
+    /// Flush all changes, and re-open the same file as a read-only cache, e.g. for the serving phase
+    /// after the cache has been built. Any clones of this cache keep their own writable map.
+    pub fn reopen_readonly(self) -> OsmNodeCacheResult<DenseFileCache> {
+        self.flush()?;
+        let mut opts = self.opts.clone();
+        opts.write = false;
+        drop(self);
+        opts.open()
+    }
+
+    /// Whether values can be set, see [`DenseFileCacheOpts::write`]
+    #[must_use]
+    pub fn is_writable(&self) -> bool {
+        self.opts.write
+    }
+
+    /// Path of the cache file, as given to [`DenseFileCacheOpts::new`]
+    #[must_use]
+    pub fn file_path(&self) -> &Path {
+        &self.opts.filename
+    }
+
+    /// Get a raw pointer to the cached values and their count, e.g. to pass to a foreign function
+    /// without copying. No lock is held after this call returns.
+    /// Values are in the stored byte order, see [`DenseFileCacheOpts::endianness`].
+    ///
+    /// # Safety
+    /// The pointer is only valid until the memory map is re-created, i.e. the cache grows,
+    /// [`remap`](Self::remap) is called, or the last clone of this cache is dropped.
+    /// The caller must prevent all of these while the pointer is in use, and must treat the data as read-only.
+    /// Values may still be modified concurrently by other accessors.
+    #[must_use]
+    pub unsafe fn leak_slice(&self) -> (*const u64, usize) {
+        let reader = self.reader();
+        (reader.raw_data.as_ptr().cast(), reader.len())
+    }
+
+    /// Fraction of slots in `0..=max_index` holding a non-zero value, computed with a parallel scan.
+    /// Slots beyond the end of the file count as empty.
+    #[must_use]
+    pub fn occupancy(&self, max_index: usize) -> f64 {
+        let reader = self.reader();
+        let end = reader.len().min(max_index.saturating_add(1));
+        let used = reader.raw_data[..end]
+            .par_iter()
+            .filter(|v| v.load(Ordering::Relaxed) != 0)
+            .count();
+        used as f64 / (max_index as f64 + 1.0)
+    }
+
+    /// Find the largest index holding a non-zero value using a parallel scan,
+    /// or `None` if all values are zero.
+    #[must_use]
+    pub fn find_max_index(&self) -> Option<usize> {
+        self.reader()
+            .raw_data
+            .par_iter()
+            .position_last(|v| v.load(Ordering::Relaxed) != 0)
+    }
+
+    /// Reset slots `start_index..end_index` to zero (missing) in parallel, e.g. to recycle
+    /// a preallocated file. The range is clamped to the current file size, and the file never grows.
+    /// Each slot is cleared atomically, so a concurrent `set` of the same slot is either kept or cleared,
+    /// depending on which of the two stores comes last.
+    pub fn zero_range(&self, start_index: usize, end_index: usize) {
+        self.assert_writable();
+        let reader = self.reader();
+        let end = end_index.min(reader.len());
+        if start_index < end {
+            reader.raw_data[start_index..end]
+                .par_iter()
+                .for_each(|v| v.store(0, Ordering::Relaxed));
+            self.mark_dirty();
+        }
+    }
+
+    /// Compute a content checksum of the stored bytes of slots `0..=max_index` in parallel,
+    /// e.g. to verify that a copy of the file is identical. Slots beyond the end of the file count as zeros,
+    /// and trailing zero slots are not hashed, so the result does not depend on the file size,
+    /// and a `max_index` beyond the end of the file, up to `usize::MAX`, costs nothing extra.
+    /// The result only depends on the file content, see [`CHECKSUM_ALGORITHM`] for details.
+    #[must_use]
+    pub fn checksum(&self, max_index: usize) -> u64 {
+        let reader = self.reader();
+        let end = reader.len().min(max_index.saturating_add(1));
+        let count = reader.raw_data[..end]
+            .par_iter()
+            .position_last(|v| v.load(Ordering::Relaxed) != 0)
+            .map_or(0, |last| last + 1);
+        let hashes: Vec<u64> = reader.raw_data[..count]
+            .par_chunks(CHECKSUM_CHUNK)
+            .map(|chunk| {
+                let hash = chunk.iter().fold(0, |h, v| {
+                    // The stored bytes, independent of the platform byte order
+                    murmur3_mix(h, u64::from_le(v.load(Ordering::Relaxed)))
+                });
+                murmur3_finish(hash, chunk.len())
+            })
+            .collect();
+        let hash = hashes.iter().fold(0, |h, &v| murmur3_mix(h, v));
+        murmur3_finish(hash, hashes.len())
+    }
+
+    /// Write slots `0..=max_index` into a new cache file, dropping the empty tail, and open it with `opts`,
+    /// e.g. to ship a minimal copy of the cache after an import. The values are converted to the byte order
+    /// of `opts`. The new file is sized exactly for the values, unless it is smaller than one page of `opts`,
+    /// in which case opening it grows it to a whole page. An existing file is overwritten,
+    /// but the cache cannot be compacted into its own file.
+    pub fn compact_into(&self, opts: DenseFileCacheOpts) -> OsmNodeCacheResult<DenseFileCache> {
+        let filename = opts.filename.as_ref();
+        let to_err = |e| OsmNodeCacheError::InvalidCacheFile(filename.to_path_buf(), e);
+        if let (Ok(target), Ok(source)) = (
+            fs::canonicalize(filename),
+            fs::canonicalize(self.opts.filename.as_ref()),
+        ) {
+            if target == source {
+                return Err(to_err(std::io::Error::new(
+                    ErrorKind::AlreadyExists,
+                    "cannot compact a cache into its own file",
+                )));
+            }
+        }
+        {
+            // The file never shrinks, so the slots stay within the reader
+            let count = self.find_max_index().map_or(0, |max| max + 1);
+            let reader = self.try_reader()?;
+            let mut writer = BufWriter::new(File::create(filename).map_err(to_err)?);
+            for value in &reader.raw_data[..count] {
+                let value = self.opts.endian.decode(value.load(Ordering::Relaxed));
+                writer.write_all(&opts.endian.encode(value).to_ne_bytes())?;
+            }
+            writer.into_inner().map_err(IntoInnerError::into_error)?;
+        }
+        opts.open()
+    }
+
+    /// Create a write-overlay over this cache: reads fall through to the file,
+    /// while writes are kept in memory and never modify or grow the file.
+    #[must_use]
+    pub fn overlay(&self) -> OverlayCache {
+        OverlayCache::new(HashMapCache::new(), self.clone())
+    }
+
+    /// Create a read-only view of the cache. Panics if the lock is poisoned, see [`try_reader`](Self::try_reader).
+    pub fn reader(&self) -> CacheReader<'_> {
+        self.try_reader().unwrap_or_else(|e| panic!("{e}"))
+    }
+
+    /// Same as [`reader`](Self::reader), but returns [`OsmNodeCacheError::LockPoisoned`]
+    /// if another thread has panicked while growing the file
+    pub fn try_reader(&self) -> OsmNodeCacheResult<CacheReader<'_>> {
+        let (_mm_guard, raw_data) = lock_and_link(&self.memmap)?;
+        Ok(CacheReader {
+            _mm_guard,
+            raw_data,
+            endian: self.opts.endian,
+        })
+    }
+
+    /// Set every slot up to the current file size to `value` in parallel, e.g. to use a non-zero
+    /// sentinel for unset values. Note that only zero slots are reported as missing by `try_get`.
+    pub fn fill(&self, value: u64) {
+        self.assert_writable();
+        let value = self.opts.endian.encode(value);
+        self.reader()
+            .raw_data
+            .par_iter()
+            .for_each(|v| v.store(value, Ordering::Relaxed));
+        self.mark_dirty();
+    }
+
+    fn assert_writable(&self) {
+        assert!(
+            self.opts.write,
+            "Cache file {} is read-only",
+            self.opts.filename.display()
+        );
+    }
+
+    /// Whether both caches use the same file, e.g. because one is a clone of the other
+    pub(crate) fn same_file(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.memmap, &other.memmap) || self.opts.filename == other.opts.filename
+    }
+
+    /// Create a cache from a memory map set up by the caller, e.g. over a shared memory segment.
+    /// The options are used as is, without applying the memory advice or resizing the map.
+    /// Growing the cache flushes the map if [`DenseFileCacheOpts::flush_on_grow`] is set, and replaces it
+    /// with a new map of `opts` filename, even on Linux, where caches opened from a file extend their map in place.
+    /// Values stored in a map of other memory, e.g. a shared memory segment, are not copied into the file,
+    /// so the map should either be created from that file, or never be written beyond its size.
+    #[must_use]
+    pub fn from_mmap(mmap: MmapMut, opts: DenseFileCacheOpts) -> Self {
+        let mut cache = Self::from_raw(mmap.into(), opts);
+        cache.file_map = false;
+        // The map may have been modified before it was passed in
+        cache.mark_dirty();
+        cache
+    }
+
+    fn from_raw(mmap: MmapRaw, opts: DenseFileCacheOpts) -> Self {
+        Self {
+            opts,
+            memmap: Arc::new(RwLock::new(mmap)),
+            mutex: Arc::new(Mutex::new(())),
+            created: false,
+            grow_count: Arc::new(AtomicU64::new(0)),
+            dirty: Arc::new(AtomicBool::new(false)),
+            bytes_written: Arc::new(AtomicU64::new(0)),
+            file_map: true,
+        }
+    }
+
+    /// Get a snapshot of the cache state for monitoring. Finding the largest index requires a full scan.
+    #[must_use]
+    pub fn stats(&self) -> DenseFileStats {
+        let capacity_elements = self.len_elements();
+        DenseFileStats {
+            file_size: (capacity_elements * size_of::<u64>()) as u64,
+            capacity_elements,
+            grow_count: self.grow_count.load(Ordering::Relaxed),
+            max_index: self.find_max_index(),
+            #[cfg(unix)]
+            open_advice: self.opts.advice,
+        }
+    }
+
+    /// Whether the cache file did not exist and has been created when opening this cache,
+    /// e.g. to decide between resuming and starting from scratch.
+    /// Always false for caches created with [`from_mmap`](Self::from_mmap).
+    #[must_use]
+    pub fn was_created(&self) -> bool {
+        self.created
+    }
+
+    /// Same as [`get_accessor`](CacheStore::get_accessor), but returns the concrete accessor type,
+    /// so that `get` and `set` can be inlined in hot loops instead of being called dynamically.
+    /// Panics if the lock is poisoned, see [`try_accessor`](Self::try_accessor).
+    pub fn accessor(&self) -> CacheWriter<'_> {
+        self.try_accessor().unwrap_or_else(|e| panic!("{e}"))
+    }
+
+    /// Same as [`accessor`](Self::accessor), but returns [`OsmNodeCacheError::LockPoisoned`]
+    /// if another thread has panicked while growing the file
+    pub fn try_accessor(&self) -> OsmNodeCacheResult<CacheWriter<'_>> {
+        let (mm_setter, raw_data) = lock_and_link(&self.memmap)?;
+        Ok(CacheWriter {
+            parent: self,
+            mm_setter,
+            raw_data,
+        })
+    }
+
+    fn new_opt(opts: DenseFileCacheOpts) -> OsmNodeCacheResult<Self> {
+        let seeded = seed_file(&opts)?;
+        let max_index = if opts.sidecar_meta {
+            opts.read_meta()?
+        } else {
+            None
+        };
+        let (file, size, created) = resize_file(max_index.unwrap_or(0), &opts)?;
+        let mmap = map_file(&file, size, &opts)?;
+        let mut cache = Self::from_raw(mmap, opts);
+        cache.created = created || seeded;
+        #[cfg(unix)]
+        if cache.opts.advice != Advice::Normal {
+            cache.advise(cache.opts.advice)?;
+        }
+        Ok(cache)
+    }
+}
+
+impl Drop for DenseFileCache {
+    /// Write the metadata file when the last clone of a writable cache is dropped,
+    /// see [`DenseFileCacheOpts::sidecar_meta`]. Use [`flush`](Self::flush) to handle errors.
+    fn drop(&mut self) {
+        if self.opts.sidecar_meta
+            && self.opts.write
+            && Arc::strong_count(&self.memmap) == 1
+            && !self.memmap.is_poisoned()
+        {
+            let _ = self.opts.write_meta(self.find_max_index());
+        }
+    }
+}
+
+impl CacheStore for DenseFileCache {
+    fn get_accessor(&self) -> Box<dyn Cache + '_> {
+        Box::new(self.accessor())
+    }
+}
+
+impl<'a> CacheReader<'a> {
+    /// Get a copyable view of the values that can be moved into other threads,
+    /// e.g. one per rayon task, without taking the lock again. See [`CacheView`].
+    #[must_use]
+    pub fn view(&self) -> CacheView<'_> {
+        CacheView {
+            raw_data: self.raw_data,
+            endian: self.endian,
+        }
+    }
+
+    /// Number of elements the cache can hold without growing
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.view().len()
+    }
+
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.view().is_empty()
+    }
+
+    /// Get the value at index. Panics if the index exceeds cache size.
+    #[must_use]
+    pub fn get(&self, index: usize) -> u64 {
+        self.view().get(index)
+    }
+
+    /// Get the value at index, or `None` if it is zero or beyond cache size.
+    #[must_use]
+    pub fn try_get(&self, index: usize) -> Option<u64> {
+        self.view().try_get(index)
+    }
+
+    /// Get latitude/longitude at index, or `None` if there is no value, see [`try_get`](Self::try_get).
+    #[must_use]
+    pub fn try_get_lat_lon(&self, index: usize) -> Option<(f64, f64)> {
+        self.view().try_get_lat_lon(index)
+    }
+
+    /// Parallel iterator over the `(index, value)` pairs with a value, e.g. to export a partially
+    /// filled cache. There is no presence tracking, so same as [`try_get`](Self::try_get),
+    /// zero values are skipped. Rayon splits the slots into contiguous chunks for its workers.
+    pub fn iter_present_parallel(&self) -> impl ParallelIterator<Item = (usize, u64)> + '_ {
+        let endian = self.endian;
+        self.raw_data
+            .par_iter()
+            .enumerate()
+            .filter_map(move |(index, v)| match v.load(Ordering::Relaxed) {
+                0 => None,
+                v => Some((index, endian.decode(v))),
+            })
+    }
+
+    /// Lazily resolve node ids to their coordinates, e.g. the node list of a way.
+    /// Yields `None` for each node that is missing from the cache or beyond its size.
+    pub fn resolve_iter<'b>(
+        &'b self,
+        ids: impl Iterator<Item = u64> + 'b,
+    ) -> impl Iterator<Item = Option<(f64, f64)>> + 'b {
+        ids.map(move |id| {
+            usize::try_from(id)
+                .ok()
+                .and_then(|id| self.try_get_lat_lon(id))
+        })
+    }
+
+    /// Get a reference to the stored value at index. Panics if the index exceeds cache size.
+    /// Other threads may modify the value concurrently, so it can only be accessed atomically.
+    /// The value is in the stored byte order, see [`DenseFileCacheOpts::endianness`].
+    #[must_use]
+    pub fn get_ref(&self, index: usize) -> &AtomicU64 {
+        self.view().get_ref(index)
+    }
+
+    /// Iterate over the stored values in blocks of `chunk_elements`, the last one may be shorter,
+    /// e.g. to checksum or export the file block by block. Panics if `chunk_elements` is 0.
+    /// Pick a multiple of [`DenseFileCacheOpts::page_size`] (in elements) to keep the blocks page-aligned.
+    /// The blocks borrow this reader, so the file cannot grow until they are dropped.
+    /// Use [`view`](Self::view) to get blocks that can be sent to other threads, e.g. with `par_bridge`.
+    /// Values are in the stored byte order, see [`DenseFileCacheOpts::endianness`].
+    pub fn chunks(&self, chunk_elements: usize) -> slice::Chunks<'_, AtomicU64> {
+        self.view().chunks(chunk_elements)
+    }
+}
+
+impl<'a> CacheView<'a> {
+    /// Number of elements the cache can hold without growing
+    #[must_use]
+    pub fn len(self) -> usize {
+        self.raw_data.len()
+    }
+
+    #[must_use]
+    pub fn is_empty(self) -> bool {
+        self.raw_data.is_empty()
+    }
+
+    /// Get the value at index. Panics if the index exceeds cache size.
+    #[must_use]
+    pub fn get(self, index: usize) -> u64 {
+        self.endian
+            .decode(self.get_ref(index).load(Ordering::Relaxed))
+    }
+
+    /// Get the value at index, or `None` if it is zero or beyond cache size.
+    #[must_use]
+    pub fn try_get(self, index: usize) -> Option<u64> {
+        match self.raw_data.get(index)?.load(Ordering::Relaxed) {
+            0 => None,
+            v => Some(self.endian.decode(v)),
+        }
+    }
+
+    /// Get latitude/longitude at index, or `None` if there is no value, see [`try_get`](Self::try_get).
+    #[must_use]
+    pub fn try_get_lat_lon(self, index: usize) -> Option<(f64, f64)> {
+        self.try_get(index).map(unpack_lat_lon)
+    }
+
+    /// Same as [`CacheReader::get_ref`]
+    #[must_use]
+    pub fn get_ref(self, index: usize) -> &'a AtomicU64 {
+        assert!(
+            index < self.len(),
+            "Index {index} exceeds cache size {}",
+            self.len()
+        );
+        &self.raw_data[index]
+    }
+
+    /// Same as [`CacheReader::chunks`]
+    pub fn chunks(self, chunk_elements: usize) -> slice::Chunks<'a, AtomicU64> {
+        self.raw_data.chunks(chunk_elements)
+    }
+}
+
+/// Same as [`CacheReader::get_ref`], e.g. `reader[index].load(Ordering::Relaxed)`
+impl<'a> Index<usize> for CacheReader<'a> {
+    type Output = AtomicU64;
+
+    fn index(&self, index: usize) -> &Self::Output {
+        self.get_ref(index)
+    }
+}
+
+impl<'a> Cache for CacheWriter<'a> {
+    fn get(&self, index: usize) -> u64 {
+        self.get_ordered(index, Ordering::Relaxed)
+    }
+
+    /// Copy the whole range at once. Panics if the range exceeds cache size.
+    fn get_range(&self, range: Range<usize>) -> Vec<u64> {
+        let endian = self.parent.opts.endian;
+        self.raw_data[range]
+            .iter()
+            .map(|v| endian.decode(v.load(Ordering::Relaxed)))
+            .collect()
+    }
+
+    /// Dense file has no presence tracking, so a zero value (the content of any
+    /// slot that was never set) is reported as missing, same as an index beyond the file size.
+    fn try_get(&self, index: usize) -> Option<u64> {
+        match self.raw_data.get(index)?.load(Ordering::Relaxed) {
+            0 => None,
+            v => Some(self.parent.opts.endian.decode(v)),
+        }
+    }
+
+    /// Set value at index position in the open memory map.
+    /// The existence of this object implies it already holds a read lock
+    /// If needed, this fn will release the read lock, get a write lock to grow the file,
+    /// and re-acquire the read lock.
+    /// Note that `RwLock` is a misnomer here:
+    ///    "read" lock means we can write to memmap (OK in parallel)
+    ///    "write" lock means we can destroy memmap, grow file, and re-create memmap (exclusive)
+    /// It would be prohibitively expensive to acquire a read lock on each call.
+    fn set(&mut self, index: usize, value: u64) {
+        self.set_and_reserve(index, value, 0);
+    }
+
+    /// Same as [`set`](Self::set), but grows the file to hold `index + reserve_ahead`.
+    fn set_and_reserve(&mut self, index: usize, value: u64, reserve_ahead: usize) {
+        self.reserve(index, reserve_ahead);
+        self.store(index, value, Ordering::Relaxed);
+    }
+
+    /// Returns the error if the file could not grow, e.g. [`OsmNodeCacheError::DiskFull`].
+    /// The cache stays usable, and values that fit in the current file can still be set.
+    fn try_set(&mut self, index: usize, value: u64) -> OsmNodeCacheResult<()> {
+        self.try_reserve(index, 0)?;
+        self.store(index, value, Ordering::Relaxed);
+        Ok(())
+    }
+
+    /// Grow the file once to fit the largest index, rather than once per page
+    fn set_many_lat_lon(&mut self, items: &[(usize, f64, f64)]) {
+        self.parent.assert_writable();
+        if let Some(max_index) = items.iter().map(|&(index, _, _)| index).max() {
+            self.reserve(max_index, 0);
+        }
+        for &(index, lat, lon) in items {
+            self.store(index, pack_lat_lon(lat, lon), Ordering::Relaxed);
+        }
+    }
+}
+
+impl<'a> CacheWriter<'a> {
+    fn len(&self) -> usize {
+        self.raw_data.len()
+    }
+
+    /// Same as [`get`](Cache::get), but loads the value with the given memory ordering instead of
+    /// `Relaxed`, e.g. `Acquire` to pair with a `Release` store of another thread.
+    /// Panics if the index exceeds cache size, or if `order` is `Release` or `AcqRel`.
+    #[must_use]
+    pub fn get_ordered(&self, index: usize, order: Ordering) -> u64 {
+        assert!(
+            index < self.len(),
+            "Index {index} exceeds cache size {}",
+            self.len()
+        );
+        self.parent
+            .opts
+            .endian
+            .decode(self.raw_data[index].load(order))
+    }
+
+    /// Same as [`set`](Cache::set), but stores the value with the given memory ordering instead of
+    /// `Relaxed`, e.g. `Release` to publish the values written before it.
+    /// Panics if `order` is `Acquire` or `AcqRel`.
+    pub fn set_ordered(&mut self, index: usize, value: u64, order: Ordering) {
+        self.reserve(index, 0);
+        self.store(index, value, order);
+    }
+
+    /// Grow the file to hold `index + reserve_ahead` if it cannot hold `index`.
+    /// Panics if the cache is read-only or could not grow, so that values are only stored after this call.
+    pub(crate) fn reserve(&mut self, index: usize, reserve_ahead: usize) {
+        if let Err(e) = self.try_reserve(index, reserve_ahead) {
+            if let Some(on_grow_error) = self.parent.opts.on_grow_error {
+                on_grow_error(&e);
+            }
+            let grow_to = index.saturating_add(reserve_ahead);
+            panic!("Failed to grow the cache file to hold index {grow_to}: {e}");
+        }
+    }
+
+    /// Same as [`reserve`](Self::reserve), but returns an error if the file could not grow,
+    /// or if the lock is poisoned. This accessor keeps the read lock either way.
+    fn try_reserve(&mut self, index: usize, reserve_ahead: usize) -> OsmNodeCacheResult<()> {
+        self.parent.assert_writable();
+        if index < self.len() {
+            return Ok(());
+        }
+        // Ensure we save everything and drop the lock.
+        // Growing file size can only happen inside the write lock.
+        // We must get a separate mutex lock before the write lock because otherwise
+        // one thread could get write lock, grow, and get the read lock, while some
+        // other thread could be stuck waiting for the write lock even though the file
+        // has already been grown.
+        self.mm_setter = None;
+        let grown = self.grow(index, reserve_ahead);
+        // Hold the read lock even if it is poisoned, as the values of this accessor must stay mapped
+        let mm = self
+            .parent
+            .memmap
+            .read()
+            .unwrap_or_else(PoisonError::into_inner);
+        let (mm_setter, raw_data) = link(mm);
+        self.mm_setter = mm_setter;
+        self.raw_data = raw_data;
+        grown
+    }
+
+    /// Grow the file unless another thread has already grown it to hold the index.
+    /// The caller must not hold the read lock.
+    fn grow(&self, index: usize, reserve_ahead: usize) -> OsmNodeCacheResult<()> {
+        let p = self.parent;
+        let _pre_write_lock = p.mutex.lock()?;
+        let mut write_lock = p.memmap.write()?;
+        // The length of this accessor's map is stale if another thread has grown the file
+        // while this one was waiting for the lock, so check the current map instead
+        if index >= write_lock.len() / size_of::<u64>() {
+            grow_memmap(
+                &mut write_lock,
+                index.saturating_add(reserve_ahead),
+                &p.opts,
+                p.file_map,
+            )?;
+            p.grow_count.fetch_add(1, Ordering::Relaxed);
+        }
+        Ok(())
+    }
+
+    /// Store a value at an index that is known to fit
+    pub(crate) fn store(&mut self, index: usize, value: u64, order: Ordering) {
+        let value = self.parent.opts.endian.encode(value);
+        self.raw_data[index].store(value, order);
+        self.parent.mark_dirty();
+
+        let threshold = self.parent.opts.flush_every_bytes as u64;
+        if threshold > 0 {
+            let elem = size_of::<u64>() as u64;
+            let written = self.parent.bytes_written.fetch_add(elem, Ordering::Relaxed) + elem;
+            // This value has crossed a multiple of the threshold
+            if written % threshold < elem {
+                // Flush with the read lock this accessor already holds, as locking again could deadlock
+                let mm = self.mm_setter.as_ref().expect("the read lock is held");
+                if let Err(e) = self.parent.flush_map(mm) {
+                    panic!("Failed to flush the cache file after {written} bytes: {e}");
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::io;
+    use std::panic::{self, AssertUnwindSafe};
+    use std::path::{Path, PathBuf};
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::thread;
+
+    use memmap2::MmapMut;
+    use rayon::iter::{IntoParallelIterator, ParallelBridge, ParallelIterator};
+
+    use crate::dense_file::{fs_block_size, resize_error};
+    use crate::traits::tests::get_random_items;
+    use crate::*;
+
+    #[test]
+    fn dense_file() {
+        let test_file = "./dense_file_test.dat";
+        let _ = fs::remove_file(test_file);
+        {
+            let fc = DenseFileCacheOpts::new(PathBuf::from(test_file))
+                .page_size(8)
+                .open()
+                .unwrap();
+            let threads = 10;
+            let items = 100000;
+            (0_usize..threads)
+                .par_bridge()
+                .for_each_with(fc.clone(), |fc, _thread_id| {
                     let mut cache = fc.get_accessor();
                     for v in get_random_items(items) {
                         cache.set(v, v as u64);
                     }
                 });
+            assert_eq!(fc.len_elements(), items);
             (0_usize..threads)
                 .par_bridge()
                 .for_each_with(fc, |fc, _thread_id| {
@@ -269,4 +1630,1290 @@ mod tests {
         }
         let _ = fs::remove_file(test_file);
     }
+
+    #[test]
+    fn dense_file_concurrent_grow_and_read() {
+        let test_file = "./dense_file_concurrent_grow_and_read.dat";
+        let _ = fs::remove_file(test_file);
+        {
+            let fc = DenseFileCacheOpts::new(PathBuf::from(test_file))
+                .page_size(64 * 1024)
+                .open()
+                .unwrap();
+            let low = 10_000;
+            let high = 2_000_000;
+            let mut cache = fc.get_accessor();
+            for i in 0..low {
+                cache.set(i, i as u64 + 1);
+            }
+            drop(cache);
+
+            let writers: Vec<_> = (0..4_usize)
+                .map(|thread_id| {
+                    let fc = fc.clone();
+                    thread::spawn(move || {
+                        // Scattered ascending indexes, forcing many grows
+                        for step in 0..2_000 {
+                            let index = low + (step * 1_000 + thread_id * 7_919) % (high - low);
+                            fc.get_accessor().set(index, index as u64 + 1);
+                        }
+                    })
+                })
+                .collect();
+            let readers: Vec<_> = (0..4_usize)
+                .map(|thread_id| {
+                    let fc = fc.clone();
+                    thread::spawn(move || {
+                        for round in 0..200 {
+                            // Short-lived accessors let the writers grow the file in between
+                            let cache = fc.get_accessor();
+                            for i in 0..100 {
+                                let index = (round * 131 + i * 97 + thread_id) % low;
+                                assert_eq!(cache.get(index), index as u64 + 1);
+                            }
+                            drop(cache);
+                            let reader = fc.reader();
+                            let index = low + round * 9_973 % (high - low);
+                            if let Some(v) = reader.try_get(index) {
+                                assert_eq!(v, index as u64 + 1);
+                            }
+                        }
+                    })
+                })
+                .collect();
+            for handle in writers.into_iter().chain(readers) {
+                handle.join().unwrap();
+            }
+            // Each grow adds at least one page, a thread that has waited for another one to grow
+            // the file must not grow it again
+            let stats = fc.stats();
+            assert!(stats.grow_count > 100);
+            assert!(stats.grow_count < stats.file_size / (64 * 1024));
+            let reader = fc.reader();
+            assert!((0..low).all(|i| reader.get(i) == i as u64 + 1));
+        }
+        let _ = fs::remove_file(test_file);
+    }
+
+    #[test]
+    fn dense_file_poisoned() {
+        let test_file = "./dense_file_poisoned.dat";
+        let _ = fs::remove_file(test_file);
+        {
+            let fc = DenseFileCacheOpts::new(PathBuf::from(test_file))
+                .page_size(8)
+                .open()
+                .unwrap();
+            let memmap = fc.memmap.clone();
+            let _ = std::thread::spawn(move || {
+                let _lock = memmap.write().unwrap();
+                panic!("poison the lock");
+            })
+            .join();
+            assert!(matches!(fc.flush(), Err(OsmNodeCacheError::LockPoisoned)));
+            assert_eq!(fc.len_elements(), 1);
+        }
+        let _ = fs::remove_file(test_file);
+    }
+
+    #[test]
+    fn dense_file_try_set_poisoned() {
+        let test_file = "./dense_file_try_set_poisoned.dat";
+        let _ = fs::remove_file(test_file);
+        {
+            let fc = DenseFileCacheOpts::new(PathBuf::from(test_file))
+                .page_size(8)
+                .open()
+                .unwrap();
+            let clone = fc.clone();
+            let _ = thread::spawn(move || {
+                let _lock = clone.mutex.lock().unwrap();
+                panic!("poison the lock");
+            })
+            .join();
+            let mut cache = fc.try_accessor().unwrap();
+            assert!(matches!(
+                cache.try_set(100, 1),
+                Err(OsmNodeCacheError::LockPoisoned)
+            ));
+            // Values that fit are still stored
+            cache.try_set(0, 1).unwrap();
+            drop(cache);
+
+            let clone = fc.clone();
+            let _ = thread::spawn(move || {
+                let _lock = clone.memmap.write().unwrap();
+                panic!("poison the lock");
+            })
+            .join();
+            assert!(matches!(
+                fc.try_accessor(),
+                Err(OsmNodeCacheError::LockPoisoned)
+            ));
+            assert!(matches!(
+                fc.try_reader(),
+                Err(OsmNodeCacheError::LockPoisoned)
+            ));
+        }
+        let _ = fs::remove_file(test_file);
+    }
+
+    #[test]
+    fn dense_file_open_existing() {
+        let test_file = "./dense_file_open_existing.dat";
+        let _ = fs::remove_file(test_file);
+        assert!(matches!(
+            DenseFileCache::open_existing(PathBuf::from(test_file)),
+            Err(OsmNodeCacheError::InvalidCacheFile(..))
+        ));
+        assert!(!PathBuf::from(test_file).exists());
+        {
+            let fc = DenseFileCacheOpts::new(PathBuf::from(test_file))
+                .page_size(8)
+                .open()
+                .unwrap();
+            assert!(fc.was_created());
+            assert!(fc.clone().was_created());
+            fc.get_accessor().set(3, 42);
+            fc.flush().unwrap();
+        }
+        {
+            let fc = DenseFileCacheOpts::new(PathBuf::from(test_file))
+                .page_size(8)
+                .open()
+                .unwrap();
+            assert!(!fc.was_created());
+        }
+        {
+            let fc = DenseFileCache::open_existing(PathBuf::from(test_file)).unwrap();
+            assert!(!fc.was_created());
+            let cache = fc.get_accessor();
+            assert_eq!(cache.get(3), 42);
+            assert_eq!(cache.try_get(3), Some(42));
+            assert_eq!(cache.try_get(2), None);
+            assert_eq!(cache.try_get(usize::MAX / 8), None);
+            assert_eq!(cache.try_get(fc.reader().len()), None);
+            drop(cache);
+
+            let (ptr, len) = unsafe { fc.leak_slice() };
+            assert_eq!(len, fc.reader().len());
+            assert_eq!(unsafe { *ptr.add(3) }, 42);
+
+            let reader = fc.reader();
+            assert_eq!(reader.get(3), 42);
+            assert_eq!(reader[3].load(Ordering::Relaxed), 42);
+            assert_eq!(reader.try_get(3), Some(42));
+            assert_eq!(reader.try_get(2), None);
+            assert_eq!(reader.try_get(reader.len()), None);
+        }
+        let _ = fs::remove_file(test_file);
+    }
+
+    #[test]
+    fn dense_file_map_hints() {
+        let test_file = "./dense_file_map_hints.dat";
+        let _ = fs::remove_file(test_file);
+        {
+            let fc = DenseFileCacheOpts::new(PathBuf::from(test_file))
+                .page_size(2 * 1024 * 1024)
+                .huge_pages(true)
+                .populate(true)
+                .open()
+                .unwrap();
+            let mut cache = fc.get_accessor();
+            cache.set(1_000_000, 42);
+            assert_eq!(cache.get(1_000_000), 42);
+            drop(cache);
+            #[cfg(unix)]
+            {
+                fc.advise_range(0, 1000, Advice::Sequential).unwrap();
+                fc.advise_range(1001, usize::MAX, Advice::Random).unwrap();
+                fc.advise_range(5, 5, Advice::Normal).unwrap();
+            }
+        }
+        let _ = fs::remove_file(test_file);
+    }
+
+    #[test]
+    fn dense_file_lock_memory() {
+        let test_file = "./dense_file_lock_memory.dat";
+        let _ = fs::remove_file(test_file);
+        let result = DenseFileCacheOpts::new(PathBuf::from(test_file))
+            .page_size(4096)
+            .lock_memory(true)
+            .open();
+        match result {
+            Ok(fc) => {
+                let mut cache = fc.get_accessor();
+                cache.set(10_000, 42);
+                assert_eq!(cache.get(10_000), 42);
+            }
+            // Locking may not be permitted in the test environment
+            Err(OsmNodeCacheError::LockMemoryFailed { size, .. }) => assert_eq!(size, 4096),
+            Err(e) => panic!("{e}"),
+        }
+        let _ = fs::remove_file(test_file);
+    }
+
+    #[test]
+    fn dense_file_remap() {
+        let test_file = "./dense_file_remap.dat";
+        let _ = fs::remove_file(test_file);
+        {
+            let fc = DenseFileCacheOpts::new(PathBuf::from(test_file))
+                .page_size(8)
+                .open()
+                .unwrap();
+            assert_eq!(fc.reader().len(), 1);
+
+            // Another writer grows the same file independently
+            let other = DenseFileCacheOpts::new(PathBuf::from(test_file))
+                .page_size(8)
+                .open()
+                .unwrap();
+            other.get_accessor().set(9, 42);
+            other.flush().unwrap();
+            assert_eq!(fc.reader().len(), 1);
+
+            fc.remap().unwrap();
+            let reader = fc.reader();
+            assert_eq!(reader.len(), 10);
+            assert_eq!(reader.get(9), 42);
+        }
+        let _ = fs::remove_file(test_file);
+    }
+
+    #[test]
+    fn dense_file_occupancy() {
+        let test_file = "./dense_file_occupancy.dat";
+        let _ = fs::remove_file(test_file);
+        {
+            let fc = DenseFileCacheOpts::new(PathBuf::from(test_file))
+                .page_size(80)
+                .open()
+                .unwrap();
+            assert_eq!(fc.occupancy(9), 0.0);
+            assert_eq!(fc.find_max_index(), None);
+            let mut cache = fc.get_accessor();
+            cache.set(0, 1);
+            cache.set(5, 1);
+            drop(cache);
+            assert_eq!(fc.find_max_index(), Some(5));
+            assert_eq!(fc.occupancy(9), 0.2);
+            assert_eq!(fc.occupancy(0), 1.0);
+            assert_eq!(fc.occupancy(19), 0.1);
+        }
+        let _ = fs::remove_file(test_file);
+    }
+
+    #[test]
+    fn dense_file_zero_range() {
+        let test_file = "./dense_file_zero_range.dat";
+        let _ = fs::remove_file(test_file);
+        {
+            let fc = DenseFileCacheOpts::new(PathBuf::from(test_file))
+                .page_size(80)
+                .open()
+                .unwrap();
+            let mut cache = fc.get_accessor();
+            for i in 0..10 {
+                cache.set(i, i as u64 + 1);
+            }
+            drop(cache);
+            fc.zero_range(2, 5);
+            fc.zero_range(8, 1_000);
+            fc.zero_range(7, 3);
+            let reader = fc.reader();
+            assert_eq!(reader.len(), 10);
+            let values: Vec<_> = (0..10).map(|i| reader.get(i)).collect();
+            assert_eq!(values, vec![1, 2, 0, 0, 0, 6, 7, 8, 0, 0]);
+        }
+        let _ = fs::remove_file(test_file);
+    }
+
+    #[test]
+    fn dense_file_zero_range_concurrent() {
+        let test_file = "./dense_file_zero_range_concurrent.dat";
+        let _ = fs::remove_file(test_file);
+        {
+            let fc = DenseFileCacheOpts::new(PathBuf::from(test_file))
+                .page_size(8 * 1000)
+                .open()
+                .unwrap();
+            let writers: Vec<_> = (0..2_u64)
+                .map(|w| {
+                    let fc = fc.clone();
+                    thread::spawn(move || {
+                        let mut cache = fc.get_accessor();
+                        for round in 1..=20_u64 {
+                            for i in (w as usize..1000).step_by(2) {
+                                cache.set(i, round);
+                            }
+                        }
+                    })
+                })
+                .collect();
+            for _ in 0..50 {
+                fc.zero_range(250, 750);
+            }
+            for writer in writers {
+                writer.join().unwrap();
+            }
+            let reader = fc.reader();
+            // Slots outside of the range are never cleared, and the ones inside are either cleared or set
+            assert!((0..250).chain(750..1000).all(|i| reader.get(i) == 20));
+            assert!((250..750).all(|i| reader.get(i) <= 20));
+        }
+        let _ = fs::remove_file(test_file);
+    }
+
+    #[test]
+    fn dense_file_fill() {
+        let test_file = "./dense_file_fill.dat";
+        let _ = fs::remove_file(test_file);
+        {
+            let fc = DenseFileCacheOpts::new(PathBuf::from(test_file))
+                .page_size(80)
+                .endianness(Endian::Big)
+                .open()
+                .unwrap();
+            fc.get_accessor().set(15, 1);
+            fc.fill(u64::MAX - 1);
+            let reader = fc.reader();
+            assert_eq!(reader.len(), 20);
+            assert!((0..20).all(|i| reader.get(i) == u64::MAX - 1));
+            drop(reader);
+            fc.fill(0);
+            assert_eq!(fc.find_max_index(), None);
+        }
+        let _ = fs::remove_file(test_file);
+    }
+
+    #[test]
+    #[cfg(feature = "serde")]
+    fn dense_file_opts_serde() {
+        let opts = DenseFileCacheOpts::new(PathBuf::from("./dense_file_opts_serde.dat"))
+            .page_size(4096)
+            .endianness(Endian::Little);
+        #[cfg(unix)]
+        let opts = opts.advise(Advice::WillNeed);
+        let json = serde_json::to_value(&opts).unwrap();
+        assert_eq!(json["filename"], "./dense_file_opts_serde.dat");
+        assert_eq!(json["page_size"], 4096);
+        assert_eq!(json["endian"], "little");
+        #[cfg(unix)]
+        assert_eq!(json["advice"], "will_need");
+        let parsed: DenseFileCacheOpts = serde_json::from_value(json).unwrap();
+        assert_eq!(parsed.page_size, 4096);
+        assert_eq!(parsed.endian, Endian::Little);
+
+        let parsed: DenseFileCacheOpts =
+            serde_json::from_str(r#"{"filename": "cache.dat", "populate": true}"#).unwrap();
+        assert_eq!(parsed.filename.as_ref(), &PathBuf::from("cache.dat"));
+        assert!(parsed.populate);
+        assert!(parsed.write);
+        assert_eq!(parsed.page_size, 1024 * 1024 * 1024);
+        assert!(serde_json::from_str::<DenseFileCacheOpts>(r#"{"advice": "bogus"}"#).is_err());
+    }
+
+    #[test]
+    fn dense_file_no_flush_on_grow() {
+        let test_file = "./dense_file_no_flush_on_grow.dat";
+        // On Linux, only the map of `from_mmap` is re-created on grow, so test both ways of opening the cache
+        for from_mmap in [false, true] {
+            let _ = fs::remove_file(test_file);
+            let opts = DenseFileCacheOpts::new(PathBuf::from(test_file))
+                .page_size(80)
+                .flush_on_grow(false);
+            let fc = if from_mmap {
+                let file = fs::OpenOptions::new()
+                    .read(true)
+                    .write(true)
+                    .create(true)
+                    .truncate(true)
+                    .open(test_file)
+                    .unwrap();
+                file.set_len(80).unwrap();
+                DenseFileCache::from_mmap(unsafe { MmapMut::map_mut(&file).unwrap() }, opts)
+            } else {
+                opts.open().unwrap()
+            };
+            let mut cache = fc.get_accessor();
+            for i in 0..1000 {
+                cache.set(i, i as u64 + 1);
+            }
+            drop(cache);
+            let reader = fc.reader();
+            assert!((0..1000).all(|i| reader.get(i) == i as u64 + 1));
+            drop(reader);
+            fc.flush().unwrap();
+            let data = fs::read(test_file).unwrap();
+            assert_eq!(data.len(), 8000);
+            assert_eq!(&data[..8], &1_u64.to_ne_bytes());
+            assert_eq!(&data[8 * 999..], &1000_u64.to_ne_bytes());
+        }
+        let _ = fs::remove_file(test_file);
+    }
+
+    #[test]
+    fn page_size_validation() {
+        assert!(validate_page_size(8).is_ok());
+        assert!(validate_page_size(1024 * 1024).is_ok());
+        assert!(matches!(
+            validate_page_size(0),
+            Err(OsmNodeCacheError::InvalidPageSize { page_size: 0, .. })
+        ));
+        assert!(validate_page_size(12).is_err());
+
+        let test_file = "./page_size_validation.dat";
+        let _ = fs::remove_file(test_file);
+        let res = DenseFileCacheOpts::new(PathBuf::from(test_file))
+            .page_size(0)
+            .open();
+        assert!(matches!(
+            res,
+            Err(OsmNodeCacheError::InvalidPageSize { .. })
+        ));
+        let _ = fs::remove_file(test_file);
+    }
+
+    #[test]
+    fn dense_file_sidecar_meta() {
+        let test_file = "./dense_file_sidecar_meta.dat";
+        let meta_file = "./dense_file_sidecar_meta.dat.meta";
+        let _ = fs::remove_file(test_file);
+        let _ = fs::remove_file(meta_file);
+        let opts = || {
+            DenseFileCacheOpts::new(PathBuf::from(test_file))
+                .page_size(80)
+                .endianness(Endian::Big)
+                .sidecar_meta(true)
+        };
+        {
+            let fc = opts().open().unwrap();
+            fc.get_accessor().set(25, 250);
+            fc.flush().unwrap();
+            let meta: serde_json::Value =
+                serde_json::from_slice(&fs::read(meta_file).unwrap()).unwrap();
+            assert_eq!(meta["max_index"], 25);
+            assert_eq!(meta["element_size"], 8);
+            assert_eq!(meta["endian"], "big");
+
+            fc.get_accessor().set(35, 350);
+            drop(fc);
+            let meta: serde_json::Value =
+                serde_json::from_slice(&fs::read(meta_file).unwrap()).unwrap();
+            assert_eq!(meta["max_index"], 35);
+
+            // A truncated cache file is grown back to hold the stored largest index
+            fs::OpenOptions::new()
+                .write(true)
+                .open(test_file)
+                .unwrap()
+                .set_len(80)
+                .unwrap();
+            let fc = opts().open().unwrap();
+            assert_eq!(fc.len_elements(), 40);
+            drop(fc);
+
+            let res = opts().endianness(Endian::Little).open();
+            assert!(matches!(res, Err(OsmNodeCacheError::InvalidCacheFile(..))));
+        }
+        let _ = fs::remove_file(test_file);
+        let _ = fs::remove_file(meta_file);
+    }
+
+    #[test]
+    fn dense_file_iter_present_parallel() {
+        let test_file = "./dense_file_iter_present_parallel.dat";
+        let _ = fs::remove_file(test_file);
+        {
+            let fc = DenseFileCacheOpts::new(PathBuf::from(test_file))
+                .page_size(800)
+                .endianness(Endian::Big)
+                .open()
+                .unwrap();
+            let mut cache = fc.get_accessor();
+            for i in (0..10_000).step_by(7) {
+                cache.set(i, i as u64 + 1);
+            }
+            drop(cache);
+            let reader = fc.reader();
+            let mut present: Vec<_> = reader.iter_present_parallel().collect();
+            present.sort_unstable();
+            let expected: Vec<_> = (0..10_000).step_by(7).map(|i| (i, i as u64 + 1)).collect();
+            assert_eq!(present, expected);
+        }
+        let _ = fs::remove_file(test_file);
+    }
+
+    #[test]
+    fn dense_file_seed_from() {
+        let seed_file = "./dense_file_seed_from.seed.dat";
+        let test_file = "./dense_file_seed_from.dat";
+        let _ = fs::remove_file(seed_file);
+        let _ = fs::remove_file(test_file);
+        {
+            let seed = DenseFileCacheOpts::new(PathBuf::from(seed_file))
+                .page_size(80)
+                .open()
+                .unwrap();
+            seed.get_accessor().set(5, 50);
+            seed.flush().unwrap();
+
+            let opts = DenseFileCacheOpts::new(PathBuf::from(test_file))
+                .page_size(80)
+                .seed_from(PathBuf::from(seed_file));
+            let fc = opts.clone().open().unwrap();
+            assert!(fc.was_created());
+            let mut cache = fc.get_accessor();
+            assert_eq!(cache.get(5), 50);
+            cache.set(6, 60);
+            drop(cache);
+            drop(fc);
+            assert_eq!(seed.reader().try_get(6), None);
+
+            // An existing file is opened as is
+            let fc = opts.open().unwrap();
+            assert!(!fc.was_created());
+            assert_eq!(fc.reader().get(6), 60);
+        }
+        let _ = fs::remove_file(seed_file);
+        let _ = fs::remove_file(test_file);
+    }
+
+    #[test]
+    fn dense_file_reader_chunks() {
+        let test_file = "./dense_file_reader_chunks.dat";
+        let _ = fs::remove_file(test_file);
+        {
+            let fc = DenseFileCacheOpts::new(PathBuf::from(test_file))
+                .page_size(80)
+                .open()
+                .unwrap();
+            let mut cache = fc.get_accessor();
+            for i in 0..25 {
+                cache.set(i, i as u64);
+            }
+            drop(cache);
+            let reader = fc.reader();
+            let lens: Vec<_> = reader.chunks(10).map(<[_]>::len).collect();
+            assert_eq!(lens, vec![10, 10, 10]);
+            let sums: Vec<u64> = reader
+                .view()
+                .chunks(10)
+                .par_bridge()
+                .map(|chunk| chunk.iter().map(|v| v.load(Ordering::Relaxed)).sum())
+                .collect();
+            assert_eq!(sums.iter().sum::<u64>(), (0..25).sum::<u64>());
+        }
+        let _ = fs::remove_file(test_file);
+    }
+
+    #[test]
+    fn dense_file_reader_view() {
+        let test_file = "./dense_file_reader_view.dat";
+        let _ = fs::remove_file(test_file);
+        {
+            let fc = DenseFileCacheOpts::new(PathBuf::from(test_file))
+                .page_size(800)
+                .endianness(Endian::Big)
+                .open()
+                .unwrap();
+            let mut cache = fc.get_accessor();
+            for i in 0..1000 {
+                cache.set(i, i as u64 + 1);
+            }
+            drop(cache);
+
+            let reader = fc.reader();
+            let view = reader.view();
+            assert_eq!(view.len(), 1000);
+            assert_eq!(view.try_get(1000), None);
+            (0..1000_usize)
+                .into_par_iter()
+                .for_each_with(view, |view, i| assert_eq!(view.get(i), i as u64 + 1));
+            let sum: u64 = (0..1000_usize)
+                .into_par_iter()
+                .map_with(view, |view, i| view.get(i))
+                .sum();
+            assert_eq!(sum, 500_500);
+        }
+        let _ = fs::remove_file(test_file);
+    }
+
+    #[test]
+    fn dense_file_on_grow_error() {
+        static REPORTED: AtomicBool = AtomicBool::new(false);
+        fn on_grow_error(e: &OsmNodeCacheError) {
+            assert!(matches!(e, OsmNodeCacheError::Io(_)), "{e}");
+            REPORTED.store(true, Ordering::Relaxed);
+        }
+
+        let test_file = "./dense_file_on_grow_error.dat";
+        let _ = fs::remove_file(test_file);
+        {
+            let fc = DenseFileCacheOpts::new(PathBuf::from(test_file))
+                .page_size(80)
+                .on_grow_error(Some(on_grow_error))
+                .open()
+                .unwrap();
+            let res = panic::catch_unwind(AssertUnwindSafe(|| {
+                // No file system supports a file this large
+                fc.get_accessor().set(1 << 60, 1);
+            }));
+            assert!(res.is_err());
+            assert!(REPORTED.load(Ordering::Relaxed));
+        }
+        let _ = fs::remove_file(test_file);
+    }
+
+    #[test]
+    fn dense_file_try_clone() {
+        let test_file = "./dense_file_try_clone.dat";
+        let _ = fs::remove_file(test_file);
+        {
+            let fc = DenseFileCacheOpts::new(PathBuf::from(test_file))
+                .page_size(80)
+                .open()
+                .unwrap();
+            fc.get_accessor().set(3, 30);
+            let other = fc.try_clone().unwrap();
+            assert!(other.was_created());
+            assert_eq!(other.reader().get(3), 30);
+
+            // The reader of the original cache does not block the other one from growing
+            let reader = fc.reader();
+            let mut cache = other.get_accessor();
+            cache.set(4, 40);
+            cache.set(25, 250);
+            drop(cache);
+            assert_eq!(reader.get(4), 40);
+            assert_eq!(reader.len(), 10);
+            drop(reader);
+            assert_eq!(other.len_elements(), 30);
+            fc.remap().unwrap();
+            assert_eq!(fc.reader().get(25), 250);
+        }
+        let _ = fs::remove_file(test_file);
+    }
+
+    #[test]
+    fn dense_file_try_set() {
+        let test_file = "./dense_file_try_set.dat";
+        let _ = fs::remove_file(test_file);
+        {
+            let fc = DenseFileCacheOpts::new(PathBuf::from(test_file))
+                .page_size(80)
+                .open()
+                .unwrap();
+            let mut cache = fc.get_accessor();
+            cache.try_set(15, 150).unwrap();
+            assert!(cache.try_set(1 << 60, 1).is_err());
+            // The accessor still holds the lock and can keep setting values
+            cache.try_set(3, 30).unwrap();
+            cache.set(25, 250);
+            assert_eq!(cache.get(15), 150);
+            assert_eq!(cache.get(3), 30);
+            drop(cache);
+            assert_eq!(fc.stats().grow_count, 2);
+
+            let opts = DenseFileCacheOpts::new(PathBuf::from(test_file));
+            #[cfg(unix)]
+            {
+                let e = resize_error(io::Error::from_raw_os_error(libc::ENOSPC), &opts, 800);
+                assert!(matches!(
+                    e,
+                    OsmNodeCacheError::DiskFull {
+                        requested_size: 800,
+                        ..
+                    }
+                ));
+            }
+            let e = resize_error(io::Error::from(io::ErrorKind::WriteZero), &opts, 800);
+            assert!(matches!(e, OsmNodeCacheError::DiskFull { .. }));
+            let e = resize_error(io::Error::from(io::ErrorKind::NotFound), &opts, 800);
+            assert!(matches!(e, OsmNodeCacheError::Io(_)));
+        }
+        let _ = fs::remove_file(test_file);
+    }
+
+    #[test]
+    fn dense_file_dirty_flag() {
+        let test_file = "./dense_file_dirty_flag.dat";
+        let _ = fs::remove_file(test_file);
+        {
+            let fc = DenseFileCacheOpts::new(PathBuf::from(test_file))
+                .page_size(80)
+                .open()
+                .unwrap();
+            assert!(!fc.is_dirty());
+            fc.get_accessor().set(3, 30);
+            assert!(fc.clone().is_dirty());
+            fc.flush().unwrap();
+            assert!(!fc.is_dirty());
+            // Nothing has changed, so this flush is a no-op
+            fc.flush().unwrap();
+            assert!(!fc.is_dirty());
+
+            fc.zero_range(0, 5);
+            assert!(fc.is_dirty());
+            fc.flush().unwrap();
+            fc.fill(1);
+            assert!(fc.is_dirty());
+            fc.flush().unwrap();
+            assert_eq!(fs::read(test_file).unwrap()[..8], 1_u64.to_ne_bytes());
+
+            fc.reader()[2].store(5, Ordering::Relaxed);
+            assert!(!fc.is_dirty());
+            fc.mark_dirty();
+            assert!(fc.is_dirty());
+        }
+        let _ = fs::remove_file(test_file);
+    }
+
+    #[test]
+    fn dense_file_compact_into() {
+        let test_file = "./dense_file_compact_into.dat";
+        let compact_file = "./dense_file_compact_into.compact.dat";
+        let _ = fs::remove_file(test_file);
+        let _ = fs::remove_file(compact_file);
+        {
+            let fc = DenseFileCacheOpts::new(PathBuf::from(test_file))
+                .page_size(800)
+                .open()
+                .unwrap();
+            let mut cache = fc.get_accessor();
+            cache.set(3, 30);
+            cache.set(24, 240);
+            drop(cache);
+            assert_eq!(fc.len_elements(), 100);
+
+            let opts = DenseFileCacheOpts::new(PathBuf::from(compact_file))
+                .page_size(8)
+                .endianness(Endian::Big);
+            let compact = fc.compact_into(opts).unwrap();
+            assert_eq!(fs::metadata(compact_file).unwrap().len(), 200);
+            let reader = compact.reader();
+            assert_eq!(reader.len(), 25);
+            assert_eq!(reader.get(3), 30);
+            assert_eq!(reader.get(24), 240);
+            assert_eq!(reader[24].load(Ordering::Relaxed), 240_u64.to_be());
+            drop(reader);
+
+            let own = DenseFileCacheOpts::new(PathBuf::from(test_file));
+            assert!(matches!(
+                fc.compact_into(own),
+                Err(OsmNodeCacheError::InvalidCacheFile(..))
+            ));
+            assert_eq!(fc.reader().get(24), 240);
+        }
+        let _ = fs::remove_file(test_file);
+        let _ = fs::remove_file(compact_file);
+    }
+
+    #[test]
+    fn dense_file_flush_every_bytes() {
+        let test_file = "./dense_file_flush_every_bytes.dat";
+        let _ = fs::remove_file(test_file);
+        {
+            let fc = DenseFileCacheOpts::new(PathBuf::from(test_file))
+                .page_size(800)
+                .flush_every_bytes(80)
+                .open()
+                .unwrap();
+            let mut cache = fc.get_accessor();
+            for i in 0..9 {
+                cache.set(i, 1);
+            }
+            assert!(fc.is_dirty());
+            // The count is shared by all accessors
+            let clone = fc.clone();
+            let mut other = clone.get_accessor();
+            other.set(9, 1);
+            assert!(!fc.is_dirty());
+            cache.set(10, 1);
+            assert!(fc.is_dirty());
+        }
+        let _ = fs::remove_file(test_file);
+    }
+
+    #[test]
+    fn dense_file_drop_cache_after_flush() {
+        let test_file = "./dense_file_drop_cache_after_flush.dat";
+        let _ = fs::remove_file(test_file);
+        {
+            let fc = DenseFileCacheOpts::new(PathBuf::from(test_file))
+                .page_size(8 * 1024)
+                .drop_cache_after_flush(true)
+                .open()
+                .unwrap();
+            let mut cache = fc.get_accessor();
+            for i in 0..10_000 {
+                cache.set(i, i as u64 + 1);
+            }
+            drop(cache);
+            fc.flush().unwrap();
+            // Evicted pages are read from the file again
+            let reader = fc.reader();
+            assert!((0..10_000).all(|i| reader.get(i) == i as u64 + 1));
+            drop(reader);
+            fc.get_accessor().set(5, 50);
+            fc.flush().unwrap();
+            assert_eq!(fc.reader().get(5), 50);
+            assert_eq!(&fs::read(test_file).unwrap()[40..48], &50_u64.to_ne_bytes());
+        }
+        let _ = fs::remove_file(test_file);
+    }
+
+    #[test]
+    fn dense_file_concrete_accessor() {
+        let test_file = "./dense_file_concrete_accessor.dat";
+        let _ = fs::remove_file(test_file);
+        {
+            let fc = DenseFileCacheOpts::new(PathBuf::from(test_file))
+                .page_size(80)
+                .open()
+                .unwrap();
+            let mut cache: CacheWriter<'_> = fc.accessor();
+            cache.set(15, 150);
+            cache.set_lat_lon(3, 1.0, 2.0);
+            assert_eq!(cache.get(15), 150);
+            drop(cache);
+            assert_eq!(fc.get_accessor().get(15), 150);
+        }
+        let _ = fs::remove_file(test_file);
+    }
+
+    #[test]
+    fn dense_file_close() {
+        let test_file = "./dense_file_close.dat";
+        let meta_file = "./dense_file_close.dat.meta";
+        let _ = fs::remove_file(test_file);
+        let _ = fs::remove_file(meta_file);
+        {
+            let fc = DenseFileCacheOpts::new(PathBuf::from(test_file))
+                .page_size(80)
+                .sidecar_meta(true)
+                .open()
+                .unwrap();
+            fc.get_accessor().set(5, 50);
+            let clone = fc.clone();
+            fc.close().unwrap();
+            assert_eq!(&fs::read(test_file).unwrap()[40..48], &50_u64.to_ne_bytes());
+
+            // The clone still has the map, and writes the metadata when closed last
+            clone.get_accessor().set(15, 150);
+            assert_eq!(clone.reader().get(5), 50);
+            clone.close().unwrap();
+            let meta: serde_json::Value =
+                serde_json::from_slice(&fs::read(meta_file).unwrap()).unwrap();
+            assert_eq!(meta["max_index"], 15);
+        }
+        let _ = fs::remove_file(test_file);
+        let _ = fs::remove_file(meta_file);
+    }
+
+    #[test]
+    fn dense_file_ordered_access() {
+        let test_file = "./dense_file_ordered_access.dat";
+        let _ = fs::remove_file(test_file);
+        {
+            let fc = DenseFileCacheOpts::new(PathBuf::from(test_file))
+                .page_size(80)
+                .endianness(Endian::Big)
+                .open()
+                .unwrap();
+            let mut cache = fc.accessor();
+            cache.set(3, 30);
+            // Publish a value, growing the file
+            cache.set_ordered(15, 150, Ordering::Release);
+            assert_eq!(cache.get_ordered(15, Ordering::Acquire), 150);
+            assert_eq!(cache.get_ordered(3, Ordering::SeqCst), 30);
+            assert_eq!(cache.get(15), 150);
+            assert!(fc.is_dirty());
+        }
+        let _ = fs::remove_file(test_file);
+    }
+
+    #[test]
+    fn dense_file_size_rounding() {
+        let test_file = "./dense_file_size_rounding.dat";
+        let _ = fs::remove_file(test_file);
+        {
+            let fc = DenseFileCacheOpts::new(PathBuf::from(test_file))
+                .page_size(80)
+                .size_rounding(SizeRounding::PowerOfTwo)
+                .open()
+                .unwrap();
+            assert_eq!(fc.len_elements(), 16);
+            let mut cache = fc.get_accessor();
+            cache.set(16, 1);
+            assert_eq!(fs::metadata(test_file).unwrap().len(), 256);
+            cache.set(100, 1);
+            assert_eq!(fs::metadata(test_file).unwrap().len(), 1024);
+            // Rounding up to 2^64 bytes overflows
+            assert!(cache.try_set(1 << 60, 1).is_err());
+            drop(cache);
+            assert_eq!(fc.len_elements(), 128);
+        }
+        let _ = fs::remove_file(test_file);
+    }
+
+    #[test]
+    fn dense_file_set_and_reserve() {
+        let test_file = "./dense_file_set_and_reserve.dat";
+        let _ = fs::remove_file(test_file);
+        {
+            let fc = DenseFileCacheOpts::new(PathBuf::from(test_file))
+                .page_size(80)
+                .open()
+                .unwrap();
+            let mut cache = fc.get_accessor();
+            cache.set_and_reserve(15, 150, 100);
+            assert_eq!(cache.get(15), 150);
+            for i in 16..=115 {
+                cache.set(i, i as u64);
+            }
+            // Already large enough, so reserving does not grow the file
+            cache.set_and_reserve(20, 200, 1000);
+            drop(cache);
+            assert_eq!(fc.len_elements(), 120);
+            assert_eq!(fc.stats().grow_count, 1);
+        }
+        let _ = fs::remove_file(test_file);
+    }
+
+    #[test]
+    fn dense_file_opts_with_filename() {
+        let files = [
+            "./dense_file_opts_shard0.dat",
+            "./dense_file_opts_shard1.dat",
+        ];
+        for file in files {
+            let _ = fs::remove_file(file);
+        }
+        {
+            let opts = DenseFileCacheOpts::new(PathBuf::from(files[0])).page_size(80);
+            let shard = opts.clone().with_filename(PathBuf::from(files[1]));
+            assert_eq!(opts.filename(), Path::new(files[0]));
+            assert_eq!(shard.filename(), Path::new(files[1]));
+            let fc = shard.open().unwrap();
+            fc.get_accessor().set(15, 1);
+            assert_eq!(fc.file_path(), Path::new(files[1]));
+            assert_eq!(fs::metadata(files[1]).unwrap().len(), 160);
+            assert!(!Path::new(files[0]).exists());
+        }
+        for file in files {
+            let _ = fs::remove_file(file);
+        }
+    }
+
+    #[test]
+    fn dense_file_set_many_lat_lon() {
+        let test_file = "./dense_file_set_many_lat_lon.dat";
+        let _ = fs::remove_file(test_file);
+        {
+            let fc = DenseFileCacheOpts::new(PathBuf::from(test_file))
+                .page_size(80)
+                .open()
+                .unwrap();
+            let mut cache = fc.get_accessor();
+            let items: Vec<_> = (0..100).map(|i| (i * 3, 1.0, i as f64)).collect();
+            cache.set_many_lat_lon(&items);
+            cache.set_many_lat_lon(&[]);
+            for &(index, lat, lon) in &items {
+                assert_eq!(cache.get(index), encoding::pack_lat_lon(lat, lon));
+            }
+            drop(cache);
+            assert_eq!(fc.len_elements(), 300);
+            assert_eq!(fc.stats().grow_count, 1);
+
+            let mut hm = HashMapCache::new();
+            hm.set_many_lat_lon(&items[..3]);
+            assert_eq!(hm.len(), 3);
+            assert_eq!(hm.get(6), encoding::pack_lat_lon(1.0, 2.0));
+        }
+        let _ = fs::remove_file(test_file);
+    }
+
+    #[test]
+    fn dense_file_get_range() {
+        let test_file = "./dense_file_get_range.dat";
+        let _ = fs::remove_file(test_file);
+        {
+            let fc = DenseFileCacheOpts::new(PathBuf::from(test_file))
+                .page_size(80)
+                .endianness(Endian::Big)
+                .open()
+                .unwrap();
+            let mut cache = fc.get_accessor();
+            cache.set(2, 20);
+            cache.set(3, 30);
+            cache.set_lat_lon(5, 1.5, -2.5);
+            assert_eq!(cache.get_range(1..5), vec![0, 20, 30, 0]);
+            assert!(cache.get_range(3..3).is_empty());
+            let coords = cache.get_range_lat_lon(5..6);
+            assert!((coords[0].0 - 1.5).abs() < 1e-6 && (coords[0].1 + 2.5).abs() < 1e-6);
+            let result = std::panic::catch_unwind(|| fc.get_accessor().get_range(5..11));
+            assert!(result.is_err());
+        }
+        let _ = fs::remove_file(test_file);
+    }
+
+    #[test]
+    fn dense_file_stats() {
+        let test_file = "./dense_file_stats.dat";
+        let _ = fs::remove_file(test_file);
+        {
+            let fc = DenseFileCacheOpts::new(PathBuf::from(test_file))
+                .page_size(80)
+                .open()
+                .unwrap();
+            let stats = fc.stats();
+            assert_eq!(stats.file_size, 80);
+            assert_eq!(stats.capacity_elements, 10);
+            assert_eq!(stats.grow_count, 0);
+            assert_eq!(stats.max_index, None);
+            #[cfg(unix)]
+            {
+                assert_eq!(stats.open_advice, Advice::Normal);
+                fc.advise(Advice::Random).unwrap();
+                assert_eq!(fc.stats().open_advice, Advice::Normal);
+            }
+
+            let mut cache = fc.get_accessor();
+            cache.set(5, 1);
+            cache.set(15, 1);
+            cache.set(35, 1);
+            drop(cache);
+            let stats = fc.clone().stats();
+            assert_eq!(stats.file_size, 320);
+            assert_eq!(stats.capacity_elements, 40);
+            assert_eq!(stats.grow_count, 2);
+            assert_eq!(stats.max_index, Some(35));
+            assert_eq!(
+                fc.to_string(),
+                "DenseFileCache(file=./dense_file_stats.dat, 40 elements, 320B)"
+            );
+            assert_eq!(fc.file_path(), Path::new(test_file));
+        }
+        let _ = fs::remove_file(test_file);
+    }
+
+    #[test]
+    fn dense_file_auto_page_size() {
+        let opts = DenseFileCacheOpts::new(PathBuf::from("./dense_file_auto_page_size.dat"))
+            .auto_page_size();
+        assert!(opts.page_size >= 256 * 1024 * 1024);
+        assert_eq!(opts.page_size % 8, 0);
+        if let Some(block) = fs_block_size(opts.filename.as_ref()) {
+            assert_eq!(opts.page_size % block, 0);
+        }
+    }
+
+    #[test]
+    fn dense_file_endianness() {
+        let test_file = "./dense_file_endianness.dat";
+        let _ = fs::remove_file(test_file);
+        {
+            let fc = DenseFileCacheOpts::new(PathBuf::from(test_file))
+                .page_size(16)
+                .endianness(Endian::Big)
+                .open()
+                .unwrap();
+            let mut cache = fc.get_accessor();
+            cache.set(1, 0x0102_0304_0506_0708);
+            assert_eq!(cache.get(1), 0x0102_0304_0506_0708);
+            assert_eq!(cache.try_get(1), Some(0x0102_0304_0506_0708));
+            drop(cache);
+            assert_eq!(fc.reader().get(1), 0x0102_0304_0506_0708);
+            fc.flush().unwrap();
+        }
+        let data = fs::read(test_file).unwrap();
+        assert_eq!(&data[8..16], &[1, 2, 3, 4, 5, 6, 7, 8]);
+        let _ = fs::remove_file(test_file);
+    }
+
+    #[test]
+    fn dense_file_readonly() {
+        let test_file = "./dense_file_readonly.dat";
+        let _ = fs::remove_file(test_file);
+        assert!(matches!(
+            DenseFileCacheOpts::new(PathBuf::from(test_file))
+                .write(false)
+                .open(),
+            Err(OsmNodeCacheError::InvalidCacheFile(..))
+        ));
+        {
+            let fc = DenseFileCacheOpts::new(PathBuf::from(test_file))
+                .page_size(80)
+                .open()
+                .unwrap();
+            assert!(fc.is_writable());
+            fc.get_accessor().set(3, 30);
+            let ro = fc.reopen_readonly().unwrap();
+            assert!(!ro.is_writable());
+            assert_eq!(ro.reader().get(3), 30);
+            assert_eq!(ro.get_accessor().try_get(3), Some(30));
+            let result = std::panic::catch_unwind(|| ro.get_accessor().set(3, 1));
+            assert!(result.is_err());
+            let result = std::panic::catch_unwind(|| ro.zero_range(0, 10));
+            assert!(result.is_err());
+            assert_eq!(ro.reader().get(3), 30);
+            ro.remap().unwrap();
+            assert_eq!(ro.len_elements(), 10);
+
+            let ro = DenseFileCacheOpts::new(PathBuf::from(test_file))
+                .write(false)
+                .open()
+                .unwrap();
+            assert_eq!(ro.get_accessor().get(3), 30);
+            assert_eq!(ro.overlay().get_accessor().get(3), 30);
+        }
+        let _ = fs::remove_file(test_file);
+    }
+
+    #[test]
+    fn dense_file_from_mmap_grow() {
+        let test_file = "./dense_file_from_mmap_grow.dat";
+        let other_file = "./dense_file_from_mmap_grow_other.dat";
+        let _ = fs::remove_file(test_file);
+        let _ = fs::remove_file(other_file);
+        {
+            // A map of another file must not be extended beyond the end of that file
+            let file = fs::OpenOptions::new()
+                .read(true)
+                .write(true)
+                .create(true)
+                .truncate(true)
+                .open(other_file)
+                .unwrap();
+            file.set_len(64).unwrap();
+            let mmap = unsafe { MmapMut::map_mut(&file).unwrap() };
+            let opts = DenseFileCacheOpts::new(PathBuf::from(test_file)).page_size(64);
+            let fc = DenseFileCache::from_mmap(mmap, opts);
+            let mut cache = fc.get_accessor();
+            cache.set(1, 42);
+            cache.set(100, 43);
+            assert_eq!(cache.get(100), 43);
+            assert_eq!(cache.get(1), 0);
+            drop(cache);
+            fc.flush().unwrap();
+            assert_eq!(fs::metadata(other_file).unwrap().len(), 64);
+            let data = fs::read(test_file).unwrap();
+            assert_eq!(&data[8 * 100..8 * 101], &43_u64.to_ne_bytes());
+        }
+        let _ = fs::remove_file(test_file);
+        let _ = fs::remove_file(other_file);
+    }
+
+    #[test]
+    fn dense_file_from_mmap() {
+        let test_file = "./dense_file_from_mmap.dat";
+        let _ = fs::remove_file(test_file);
+        {
+            let file = fs::OpenOptions::new()
+                .read(true)
+                .write(true)
+                .create(true)
+                .truncate(true)
+                .open(test_file)
+                .unwrap();
+            file.set_len(64).unwrap();
+            let mmap = unsafe { MmapMut::map_mut(&file).unwrap() };
+            let opts = DenseFileCacheOpts::new(PathBuf::from(test_file)).page_size(64);
+            let fc = DenseFileCache::from_mmap(mmap, opts);
+            assert_eq!(fc.reader().len(), 8);
+            let mut cache = fc.get_accessor();
+            cache.set(7, 42);
+            assert_eq!(cache.get(7), 42);
+            cache.set(8, 43);
+            assert_eq!(cache.get(8), 43);
+            assert_eq!(cache.get(7), 42);
+        }
+        let _ = fs::remove_file(test_file);
+    }
+
+    #[test]
+    fn dense_file_checksum() {
+        let test_file = "./dense_file_checksum.dat";
+        let test_file2 = "./dense_file_checksum2.dat";
+        let _ = fs::remove_file(test_file);
+        let _ = fs::remove_file(test_file2);
+        {
+            let fc = DenseFileCacheOpts::new(PathBuf::from(test_file))
+                .page_size(8)
+                .open()
+                .unwrap();
+            let fc2 = DenseFileCacheOpts::new(PathBuf::from(test_file2))
+                .page_size(1024)
+                .open()
+                .unwrap();
+            assert_eq!(fc.checksum(10), fc2.checksum(10));
+            let mut cache = fc.get_accessor();
+            let mut cache2 = fc2.get_accessor();
+            for v in 0..2000 {
+                cache.set(v, v as u64 + 1);
+                cache2.set(v, v as u64 + 1);
+            }
+            drop(cache);
+            assert_eq!(fc.checksum(3000), fc2.checksum(3000));
+            assert_ne!(fc.checksum(1000), fc2.checksum(2000));
+            cache2.set(1500, 1);
+            drop(cache2);
+            assert_eq!(fc.checksum(1000), fc2.checksum(1000));
+            assert_ne!(fc.checksum(3000), fc2.checksum(3000));
+            // Slots past the end of the file are not scanned one by one
+            assert_eq!(fc.checksum(usize::MAX), fc.checksum(1999));
+            assert_ne!(fc.checksum(usize::MAX), fc2.checksum(usize::MAX));
+            assert_ne!(fc.checksum(1998), fc.checksum(1999));
+        }
+        let _ = fs::remove_file(test_file);
+        let _ = fs::remove_file(test_file2);
+    }
+
+    #[test]
+    fn dense_file_resolve_iter() {
+        let test_file = "./dense_file_resolve_iter.dat";
+        let _ = fs::remove_file(test_file);
+        {
+            let fc = DenseFileCacheOpts::new(PathBuf::from(test_file))
+                .page_size(64)
+                .open()
+                .unwrap();
+            let mut cache = fc.get_accessor();
+            cache.set_lat_lon(1, 1.0, 2.0);
+            cache.set_lat_lon(3, -1.0, -2.0);
+            assert_eq!(cache.try_get_lat_lon(2), None);
+            drop(cache);
+            let reader = fc.reader();
+            let coords: Vec<_> = reader
+                .resolve_iter(vec![1, 2, 3, 1_000_000].into_iter())
+                .map(|c| c.map(|(lat, lon)| (lat.round(), lon.round())))
+                .collect();
+            assert_eq!(
+                coords,
+                vec![Some((1.0, 2.0)), None, Some((-1.0, -2.0)), None]
+            );
+        }
+        let _ = fs::remove_file(test_file);
+    }
+
+    #[test]
+    #[cfg(feature = "tempfile")]
+    fn dense_file_temp() {
+        let fc = DenseFileCacheOpts::temp()
+            .unwrap()
+            .page_size(64)
+            .open()
+            .unwrap();
+        let path = fc.opts.filename.to_path_buf();
+        assert!(path.exists());
+        assert_eq!(fc.reader().len(), 8);
+        fc.get_accessor().set(100, 42);
+        assert_eq!(fc.clone().get_accessor().get(100), 42);
+        drop(fc);
+        assert!(!path.exists());
+    }
 }
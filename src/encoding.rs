@@ -0,0 +1,447 @@
+//! Encoding of latitude/longitude pairs into the `u64` values stored by the caches.
+//! Each coordinate is scaled to the full `i32` range, and the two `i32` values are packed into one `u64`.
+//! This module only depends on `core`, and is the only one available without the `std` feature.
+
+const LAT_I32_RATE: f64 = i32::MAX as f64 / 90_f64;
+const I32_LAT_RATE: f64 = 1_f64 / LAT_I32_RATE;
+const LON_I32_RATE: f64 = i32::MAX as f64 / 180_f64;
+const I32_LON_RATE: f64 = 1_f64 / LON_I32_RATE;
+
+/// How coordinates are rounded to the nearest representable `i32` value when encoding
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RoundingMode {
+    /// Round toward zero, same as an `as i32` cast. This is the default, used by [`latitude_to_i32`].
+    Trunc,
+    /// Round to the nearest value, with halfway cases away from zero
+    Nearest,
+}
+
+impl Default for RoundingMode {
+    fn default() -> Self {
+        Self::Trunc
+    }
+}
+
+impl RoundingMode {
+    #[inline]
+    fn apply(self, value: f64) -> i32 {
+        match self {
+            RoundingMode::Trunc => value as i32,
+            RoundingMode::Nearest => round(value) as i32,
+        }
+    }
+}
+
+/// Encode latitude in the -90..=90 range as an i32. Panics if the latitude is out of range.
+#[inline]
+#[must_use]
+pub fn latitude_to_i32(value: f64) -> i32 {
+    latitude_to_i32_with(value, RoundingMode::Trunc)
+}
+
+/// Same as [`latitude_to_i32`], but with the given rounding mode
+#[inline]
+#[must_use]
+pub fn latitude_to_i32_with(value: f64, mode: RoundingMode) -> i32 {
+    if (-90_f64..=90_f64).contains(&value) {
+        mode.apply(value * LAT_I32_RATE)
+    } else {
+        panic!("Invalid latitude {value}")
+    }
+}
+
+/// Decode latitude encoded by [`latitude_to_i32`]
+#[inline]
+#[must_use]
+pub fn i32_to_latitude(value: i32) -> f64 {
+    (value as f64) * I32_LAT_RATE
+}
+
+/// Same as [`i32_to_latitude`], but never outside of the -90..=90 range.
+/// The encoded extremes `±i32::MAX` already decode to exactly ±90, but `i32::MIN`,
+/// which is never produced by [`latitude_to_i32`], would decode to slightly less than -90.
+#[inline]
+#[must_use]
+pub fn i32_to_latitude_clamped(value: i32) -> f64 {
+    i32_to_latitude(value).clamp(-90_f64, 90_f64)
+}
+
+/// Encode longitude as an i32, normalizing values outside of the -180..=180 range.
+#[inline]
+#[must_use]
+pub fn longitude_to_i32(value: f64) -> i32 {
+    longitude_to_i32_with(value, RoundingMode::Trunc)
+}
+
+/// Same as [`longitude_to_i32`], but with the given rounding mode for values in the -180..=180 range.
+/// Values outside of it are always rounded to the nearest value.
+#[inline]
+#[must_use]
+pub fn longitude_to_i32_with(value: f64, mode: RoundingMode) -> i32 {
+    if (-180_f64..=180_f64).contains(&value) {
+        mode.apply(value * LON_I32_RATE)
+    } else {
+        // experimental
+        round(((value + 180_f64) % 360_f64 - 180_f64) * LON_I32_RATE) as i32
+    }
+}
+
+#[cfg(feature = "std")]
+#[inline]
+fn round(value: f64) -> f64 {
+    value.round()
+}
+
+#[cfg(not(feature = "std"))]
+#[inline]
+fn round(value: f64) -> f64 {
+    round_core(value)
+}
+
+/// Round half away from zero, same as `f64::round` for values in the `i64` range,
+/// which is not available in `core`
+#[cfg(any(not(feature = "std"), test))]
+#[inline]
+fn round_core(value: f64) -> f64 {
+    let truncated = (value as i64) as f64;
+    let fraction = value - truncated;
+    if fraction >= 0.5 {
+        truncated + 1.0
+    } else if fraction <= -0.5 {
+        truncated - 1.0
+    } else {
+        truncated
+    }
+}
+
+/// Decode longitude encoded by [`longitude_to_i32`]
+#[inline]
+#[must_use]
+pub fn i32_to_longitude(value: i32) -> f64 {
+    (value as f64) * I32_LON_RATE
+}
+
+/// Same as [`i32_to_longitude`], but never outside of the -180..=180 range, see [`i32_to_latitude_clamped`]
+#[inline]
+#[must_use]
+pub fn i32_to_longitude_clamped(value: i32) -> f64 {
+    i32_to_longitude(value).clamp(-180_f64, 180_f64)
+}
+
+/// Split a u64 value into its high and low u32 halves
+#[inline]
+#[must_use]
+pub fn unpack_u32s(value: u64) -> (u32, u32) {
+    ((value >> 32) as u32, value as u32)
+}
+
+/// Pack two u32 values into a u64 value, `high` in the upper 32 bits
+#[inline]
+#[must_use]
+pub fn pack_u32s(high: u32, low: u32) -> u64 {
+    u64::from(high) << 32 | u64::from(low)
+}
+
+/// Split a u64 value into its high and low i32 halves
+#[inline]
+#[must_use]
+pub fn unpack_i32s(value: u64) -> (i32, i32) {
+    let (high, low) = unpack_u32s(value);
+    (high as i32, low as i32)
+}
+
+/// Pack two i32 values into a u64 value, `high` in the upper 32 bits
+#[inline]
+#[must_use]
+pub fn pack_i32s(high: i32, low: i32) -> u64 {
+    pack_u32s(high as u32, low as u32)
+}
+
+/// Encode latitude and longitude as a single u64 value, the way [`crate::Cache::set_lat_lon`] stores them
+#[inline]
+#[must_use]
+pub fn pack_lat_lon(lat: f64, lon: f64) -> u64 {
+    pack_lat_lon_with(lat, lon, RoundingMode::Trunc)
+}
+
+/// Same as [`pack_lat_lon`], but with the given rounding mode
+#[inline]
+#[must_use]
+pub fn pack_lat_lon_with(lat: f64, lon: f64, mode: RoundingMode) -> u64 {
+    pack_i32s(
+        latitude_to_i32_with(lat, mode),
+        longitude_to_i32_with(lon, mode),
+    )
+}
+
+/// Decode latitude and longitude from a u64 value, the way [`crate::Cache::get_lat_lon`] reads them
+#[inline]
+#[must_use]
+pub fn unpack_lat_lon(value: u64) -> (f64, f64) {
+    let (lat, lon) = unpack_i32s(value);
+    (i32_to_latitude(lat), i32_to_longitude(lon))
+}
+
+/// Same as [`unpack_lat_lon`], but clamps the coordinates to their valid ranges, e.g. for values
+/// written by other tools
+#[inline]
+#[must_use]
+pub fn unpack_lat_lon_clamped(value: u64) -> (f64, f64) {
+    let (lat, lon) = unpack_i32s(value);
+    (i32_to_latitude_clamped(lat), i32_to_longitude_clamped(lon))
+}
+
+/// Map a signed value to an unsigned one so that small magnitudes stay small, as used by protobuf `sint64`
+#[inline]
+#[must_use]
+pub fn zigzag_encode(value: i64) -> u64 {
+    ((value << 1) ^ (value >> 63)) as u64
+}
+
+/// Decode a value encoded by [`zigzag_encode`]
+#[inline]
+#[must_use]
+pub fn zigzag_decode(value: u64) -> i64 {
+    ((value >> 1) as i64) ^ -((value & 1) as i64)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::panic;
+    use std::panic::{catch_unwind, UnwindSafe};
+
+    use crate::encoding::{
+        i32_to_latitude, i32_to_latitude_clamped, i32_to_longitude, i32_to_longitude_clamped,
+        latitude_to_i32, latitude_to_i32_with, longitude_to_i32, longitude_to_i32_with, pack_i32s,
+        pack_lat_lon, pack_lat_lon_with, pack_u32s, round_core, unpack_i32s, unpack_lat_lon,
+        unpack_lat_lon_clamped, unpack_u32s, zigzag_decode, zigzag_encode, RoundingMode,
+        LAT_I32_RATE, LON_I32_RATE,
+    };
+
+    const EPSILON: f64 = f32::EPSILON as f64;
+
+    fn eq(a: f64, b: f64) -> bool {
+        (a - b).abs() > EPSILON
+    }
+
+    fn assert_floats(expected: f64, actual: f64) {
+        if eq(expected, actual) {
+            panic!(
+                "Assert failed: expected={expected}, actual={actual}, delta={}",
+                (expected - actual).abs()
+            );
+        }
+    }
+
+    macro_rules! test_lat {
+        ( $stored:expr, $value:expr, $expected:expr ) => {
+            assert_eq!($stored, latitude_to_i32($value));
+            assert_floats($expected, i32_to_latitude(latitude_to_i32($value)));
+        };
+        ( $stored:expr, $value:expr ) => {
+            assert_eq!($stored, latitude_to_i32($value));
+            assert_floats($value, i32_to_latitude(latitude_to_i32($value)));
+        };
+    }
+    macro_rules! test_lon {
+        ( $stored:expr, $value:expr, $expected:expr ) => {
+            assert_eq!($stored, longitude_to_i32($value));
+            assert_floats($expected, i32_to_longitude(longitude_to_i32($value)));
+        };
+        ( $stored:expr, $value:expr ) => {
+            assert_eq!($stored, longitude_to_i32($value));
+            assert_floats($value, i32_to_longitude(longitude_to_i32($value)));
+        };
+    }
+
+    fn assert_panic<F: FnOnce() -> R + UnwindSafe, R: std::fmt::Debug>(f: F) {
+        let handler = panic::take_hook();
+        panic::set_hook(Box::new(|_| {}));
+        let res = catch_unwind(f);
+        panic::set_hook(handler);
+        assert!(
+            res.is_err(),
+            "Expected a panic, but received {:?} instead",
+            res.unwrap()
+        );
+    }
+
+    #[test]
+    fn test_latitude() {
+        test_lat!(0, 0.0);
+        test_lat!(0, 0.00000001);
+        test_lat!(0, -0.00000001);
+        test_lat!(2, 0.0000001);
+        test_lat!(-2, -0.0000001);
+        test_lat!(23, 0.000001);
+        test_lat!(-23, -0.000001);
+        test_lat!(238, 0.00001);
+        test_lat!(-238, -0.00001);
+        test_lat!(2386, 0.0001);
+        test_lat!(-2386, -0.0001);
+        test_lat!(23860, 0.001);
+        test_lat!(-23860, -0.001);
+        test_lat!(238609, 0.01);
+        test_lat!(2386092, 0.1);
+        test_lat!(23860929, 1.0);
+        test_lat!(2147483408, 89.99999);
+        test_lat!(2147483623, 89.999999);
+        test_lat!(2147483644, 89.9999999);
+        test_lat!(2147483646, 89.99999999);
+        test_lat!(2147483646, 89.999999999);
+        test_lat!(2147483644, 90_f64 - EPSILON);
+        test_lat!(-2147483644, -90_f64 + EPSILON);
+        test_lat!(2147483647, 90.0);
+
+        assert_panic(|| latitude_to_i32(90_f64 + EPSILON));
+        assert_panic(|| latitude_to_i32(-90_f64 - EPSILON));
+    }
+
+    #[test]
+    fn test_longitude() {
+        test_lon!(0, 0.0);
+        test_lon!(0, 0.00000001);
+        test_lon!(0, -0.00000001);
+        test_lon!(1, 0.0000001);
+        test_lon!(-1, -0.0000001);
+        test_lon!(23, 0.000002);
+        test_lon!(-23, -0.000002);
+        test_lon!(119, 0.00001);
+        test_lon!(-119, -0.00001);
+        test_lon!(1193, 0.0001);
+        test_lon!(-1193, -0.0001);
+        test_lon!(11930, 0.001);
+        test_lon!(-11930, -0.001);
+        test_lon!(119304, 0.01);
+        test_lon!(1193046, 0.1);
+        test_lon!(11930464, 1.0);
+        test_lon!(2147483527, 179.99999);
+        test_lon!(2147483635, 179.999999);
+        test_lon!(2147483645, 179.9999999);
+        test_lon!(2147483646, 179.99999999);
+        test_lon!(2147483646, 179.999999999);
+        test_lon!(2147483647, 180.0);
+        test_lon!(-2147483647, 180.00000001, -180.0);
+        test_lon!(-2147483646, 180.0000001, -180.0);
+        test_lon!(-1908874353, 200.0, -160.0);
+    }
+
+    macro_rules! test_pack {
+        ( $high:expr, $low:expr ) => {{
+            let (high, low) = unpack_i32s(pack_i32s($high, $low));
+            assert_eq!((high, low), ($high, $low));
+        }};
+    }
+
+    #[test]
+    fn test_pack() {
+        test_pack!(0, 0);
+        test_pack!(1, 2);
+        test_pack!(2, 1);
+        test_pack!(-1, 1);
+        test_pack!(1, -1);
+        test_pack!(i32::MAX, -1);
+        test_pack!(-1, i32::MAX);
+        test_pack!(i32::MAX, i32::MIN);
+        test_pack!(i32::MIN, i32::MAX);
+    }
+
+    #[test]
+    fn test_pack_u32s() {
+        assert_eq!(pack_u32s(1, 2), 0x0000_0001_0000_0002);
+        assert_eq!(unpack_u32s(pack_u32s(u32::MAX, 0)), (u32::MAX, 0));
+        assert_eq!(unpack_u32s(pack_u32s(0, u32::MAX)), (0, u32::MAX));
+        assert_eq!(pack_i32s(-1, 1), pack_u32s(u32::MAX, 1));
+    }
+
+    #[test]
+    fn test_pack_lat_lon() {
+        let (lat, lon) = unpack_lat_lon(pack_lat_lon(12.345, -67.891));
+        assert_floats(12.345, lat);
+        assert_floats(-67.891, lon);
+        assert_eq!(
+            pack_lat_lon(1.0, 2.0),
+            pack_i32s(latitude_to_i32(1.0), longitude_to_i32(2.0))
+        );
+    }
+
+    #[test]
+    fn test_zigzag() {
+        assert_eq!(zigzag_encode(0), 0);
+        assert_eq!(zigzag_encode(-1), 1);
+        assert_eq!(zigzag_encode(1), 2);
+        assert_eq!(zigzag_encode(-2), 3);
+        assert_eq!(zigzag_encode(i64::MAX), u64::MAX - 1);
+        assert_eq!(zigzag_encode(i64::MIN), u64::MAX);
+        for v in [0, 1, -1, 12345, -12345, i64::MAX, i64::MIN] {
+            assert_eq!(zigzag_decode(zigzag_encode(v)), v);
+        }
+    }
+
+    #[test]
+    fn test_round_core() {
+        for v in [
+            0.0,
+            0.4,
+            0.5,
+            0.6,
+            1.5,
+            2.5,
+            -0.4,
+            -0.5,
+            -0.6,
+            -2.5,
+            1e9 + 0.5,
+            -1e9 - 0.5,
+            2_147_483_646.7,
+        ] {
+            assert_eq!(round_core(v), v.round(), "{v}");
+        }
+    }
+
+    #[test]
+    fn test_boundaries() {
+        // The extremes round-trip exactly, with either decoder
+        for value in [-90_f64, 90_f64] {
+            let stored = latitude_to_i32(value);
+            assert_eq!(stored, if value > 0.0 { i32::MAX } else { -i32::MAX });
+            assert_eq!(i32_to_latitude(stored), value);
+            assert_eq!(i32_to_latitude_clamped(stored), value);
+        }
+        for value in [-180_f64, 180_f64] {
+            let stored = longitude_to_i32(value);
+            assert_eq!(i32_to_longitude(stored), value);
+            assert_eq!(i32_to_longitude_clamped(stored), value);
+        }
+        assert!(i32_to_latitude(i32::MIN) < -90.0);
+        assert!(i32_to_longitude(i32::MIN) < -180.0);
+        assert_eq!(i32_to_latitude_clamped(i32::MIN), -90.0);
+        assert_eq!(i32_to_longitude_clamped(i32::MIN), -180.0);
+        assert_eq!(
+            unpack_lat_lon_clamped(pack_i32s(i32::MIN, i32::MIN)),
+            (-90.0, -180.0)
+        );
+        assert_eq!(
+            unpack_lat_lon_clamped(pack_lat_lon(12.5, -7.25)),
+            unpack_lat_lon(pack_lat_lon(12.5, -7.25))
+        );
+    }
+
+    #[test]
+    fn test_rounding_mode() {
+        use RoundingMode::{Nearest, Trunc};
+        assert_eq!(RoundingMode::default(), Trunc);
+        // Just above the halfway point between two encoded values
+        let lat = 1000.6 / LAT_I32_RATE;
+        assert_eq!(latitude_to_i32_with(lat, Trunc), 1000);
+        assert_eq!(latitude_to_i32_with(lat, Nearest), 1001);
+        assert_eq!(latitude_to_i32_with(-lat, Nearest), -1001);
+        assert_eq!(latitude_to_i32_with(lat, Trunc), latitude_to_i32(lat));
+        let lon = 1000.4 / LON_I32_RATE;
+        assert_eq!(longitude_to_i32_with(lon, Nearest), 1000);
+        assert_eq!(longitude_to_i32_with(-lon, Trunc), -1000);
+        assert_eq!(latitude_to_i32_with(90.0, Nearest), i32::MAX);
+        assert_eq!(pack_lat_lon_with(lat, lon, Nearest), pack_i32s(1001, 1000));
+        assert_eq!(pack_lat_lon_with(lat, lon, Trunc), pack_lat_lon(lat, lon));
+    }
+}
@@ -0,0 +1,61 @@
+//! Helpers for working with decoded `(latitude, longitude)` pairs, e.g. from [`crate::Cache::get_lat_lon`].
+
+/// Mean Earth radius in meters, as used by the haversine formula
+const EARTH_RADIUS_M: f64 = 6_371_008.8;
+
+/// Great-circle distance in meters between two `(lat, lon)` points, using the haversine formula.
+#[must_use]
+pub fn haversine_m(a: (f64, f64), b: (f64, f64)) -> f64 {
+    let (lat1, lon1) = (a.0.to_radians(), a.1.to_radians());
+    let (lat2, lon2) = (b.0.to_radians(), b.1.to_radians());
+    let h = ((lat2 - lat1) / 2.0).sin().powi(2)
+        + lat1.cos() * lat2.cos() * ((lon2 - lon1) / 2.0).sin().powi(2);
+    2.0 * EARTH_RADIUS_M * h.sqrt().min(1.0).asin()
+}
+
+/// Bounding box of `(lat, lon)` points as `(min_lat, min_lon, max_lat, max_lon)`.
+/// An empty iterator produces an inverted box of infinities, which contains no points.
+#[must_use]
+pub fn bbox_of(coords: impl Iterator<Item = (f64, f64)>) -> (f64, f64, f64, f64) {
+    coords.fold(
+        (
+            f64::INFINITY,
+            f64::INFINITY,
+            f64::NEG_INFINITY,
+            f64::NEG_INFINITY,
+        ),
+        |(min_lat, min_lon, max_lat, max_lon), (lat, lon)| {
+            (
+                min_lat.min(lat),
+                min_lon.min(lon),
+                max_lat.max(lat),
+                max_lon.max(lon),
+            )
+        },
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::geo_util::{bbox_of, haversine_m};
+
+    #[test]
+    fn test_haversine() {
+        assert_eq!(haversine_m((10.0, 20.0), (10.0, 20.0)), 0.0);
+        // One degree of latitude is about 111.2 km
+        assert!((haversine_m((0.0, 0.0), (1.0, 0.0)) - 111_195.0).abs() < 1.0);
+        // Crossing the antimeridian takes the short way around
+        assert!((haversine_m((0.0, 179.5), (0.0, -179.5)) - 111_195.0).abs() < 1.0);
+        // Pole to pole is half of the circumference
+        let half = std::f64::consts::PI * 6_371_008.8;
+        assert!((haversine_m((90.0, 0.0), (-90.0, 0.0)) - half).abs() < 1.0);
+    }
+
+    #[test]
+    fn test_bbox() {
+        let coords = vec![(1.0, 2.0), (-3.0, 4.0), (5.0, -6.0)];
+        assert_eq!(bbox_of(coords.into_iter()), (-3.0, -6.0, 5.0, 4.0));
+        let (min_lat, _, max_lat, _) = bbox_of(std::iter::empty());
+        assert!(min_lat > max_lat);
+    }
+}
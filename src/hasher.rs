@@ -0,0 +1,105 @@
+use std::hash::{BuildHasher, Hasher};
+
+/// Multiplier of the Fx hash used by `rustc`
+const SEED: u64 = 0x51_7c_c1_b7_27_22_0a_95;
+
+/// Builds [`FxHasher`], the hasher of the [`crate::HashMapCache`] map.
+/// The `u64` keys do not need the `DoS` resistance of the default `SipHash`, which is much slower.
+#[derive(Clone, Copy, Debug, Default)]
+pub(crate) struct FxBuildHasher;
+
+impl BuildHasher for FxBuildHasher {
+    type Hasher = FxHasher;
+
+    #[inline]
+    fn build_hasher(&self) -> FxHasher {
+        FxHasher { hash: 0 }
+    }
+}
+
+/// A multiply-rotate hash of the `rustc` Fx family. The final rotation moves the well-mixed high bits
+/// into the low ones that pick the bucket, so that keys with a common stride do not collide.
+pub(crate) struct FxHasher {
+    hash: u64,
+}
+
+impl Hasher for FxHasher {
+    #[inline]
+    fn write(&mut self, bytes: &[u8]) {
+        for chunk in bytes.chunks(8) {
+            let mut value = [0; 8];
+            value[..chunk.len()].copy_from_slice(chunk);
+            self.write_u64(u64::from_le_bytes(value));
+        }
+    }
+
+    #[inline]
+    fn write_u64(&mut self, value: u64) {
+        self.hash = (self.hash.rotate_left(5) ^ value).wrapping_mul(SEED);
+    }
+
+    #[inline]
+    fn finish(&self) -> u64 {
+        self.hash.rotate_left(26)
+    }
+}
+
+const MURMUR3_C1: u64 = 0x87c3_7b91_1142_53d5;
+const MURMUR3_C2: u64 = 0x4cf5_ad43_2745_937f;
+
+/// One `MurmurHash3` x64 mixing step, hashing a whole 8-byte word at once
+#[inline]
+pub(crate) fn murmur3_mix(hash: u64, word: u64) -> u64 {
+    let k = word
+        .wrapping_mul(MURMUR3_C1)
+        .rotate_left(31)
+        .wrapping_mul(MURMUR3_C2);
+    (hash ^ k)
+        .rotate_left(27)
+        .wrapping_mul(5)
+        .wrapping_add(0x52dc_e729)
+}
+
+/// The `MurmurHash3` finalizer over the hash of `words` mixed words, so that every input bit
+/// affects every output bit
+#[inline]
+pub(crate) fn murmur3_finish(hash: u64, words: usize) -> u64 {
+    let mut h = hash ^ (words as u64).wrapping_mul(8);
+    h ^= h >> 33;
+    h = h.wrapping_mul(0xff51_afd7_ed55_8ccd);
+    h ^= h >> 33;
+    h = h.wrapping_mul(0xc4ce_b9fe_1a85_ec53);
+    h ^ (h >> 33)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+    use std::hash::{BuildHasher, Hasher};
+
+    use crate::hasher::{murmur3_finish, murmur3_mix, FxBuildHasher};
+
+    #[test]
+    fn strided_keys() {
+        // The low bits of the hash pick the bucket, so they must differ even for keys with a large stride
+        let low_bits: HashSet<_> = (0..1024_u64)
+            .map(|i| {
+                let mut hasher = FxBuildHasher.build_hasher();
+                hasher.write_u64(i << 20);
+                hasher.finish() & 0xfff
+            })
+            .collect();
+        assert!(low_bits.len() > 600, "{}", low_bits.len());
+    }
+
+    #[test]
+    fn murmur3_order() {
+        let hash = |words: &[u64]| {
+            let h = words.iter().fold(0, |h, &w| murmur3_mix(h, w));
+            murmur3_finish(h, words.len())
+        };
+        assert_ne!(hash(&[1, 2]), hash(&[2, 1]));
+        assert_ne!(hash(&[0]), hash(&[0, 0]));
+        assert_ne!(hash(&[1]), hash(&[2]));
+    }
+}
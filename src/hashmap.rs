@@ -1,24 +1,150 @@
-use std::fs::File;
-use std::io::{BufReader, BufWriter};
+use std::fmt;
+use std::fs::{self, File};
+use std::io::{BufRead, BufReader, BufWriter, ErrorKind, IntoInnerError, Read, Write};
+use std::mem::size_of;
+use std::ops::Range;
 use std::path::Path;
+use std::process;
+use std::sync::atomic::{AtomicUsize, Ordering};
 use std::sync::Arc;
 
+use bincode::Options;
 use dashmap::DashMap;
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use rayon::iter::{IntoParallelIterator, IntoParallelRefIterator, ParallelIterator};
+use serde::{Serialize, Serializer};
 
-use crate::traits::{open_cache_file, Cache, CacheStore};
-use crate::OsmNodeCacheResult;
+use crate::encoding::{pack_i32s, unpack_i32s, unpack_lat_lon, zigzag_decode, zigzag_encode};
+use crate::hasher::FxBuildHasher;
+use crate::traits::{format_bytes, format_count, Cache, CacheStore};
+use crate::{OsmNodeCacheError, OsmNodeCacheResult};
+
+/// How [`HashMapCache::merge`] resolves an index present in both caches
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ConflictPolicy {
+    KeepSelf,
+    KeepOther,
+    Max,
+    Min,
+}
+
+/// The map of a [`HashMapCache`], hashing its integer keys with the fast Fx hash
+type NodeMap = DashMap<u64, u64, FxBuildHasher>;
 
 #[derive(Clone, Default)]
 pub struct HashMapCache {
-    data: Arc<DashMap<u64, u64>>,
+    data: Arc<NodeMap>,
+    default: u64,
 }
 
-fn open_for_read<P: AsRef<Path>>(filename: P) -> OsmNodeCacheResult<BufReader<File>> {
-    Ok(BufReader::new(File::open(filename)?))
+/// Serializes entries as a JSON object in the given order, same format as the `DashMap` itself
+struct OrderedEntries<'a>(&'a [(u64, u64)]);
+
+impl Serialize for OrderedEntries<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_map(self.0.iter().map(|(k, v)| (k, v)))
+    }
 }
 
-fn open_for_write<P: AsRef<Path>>(filename: P) -> OsmNodeCacheResult<BufWriter<File>> {
-    Ok(BufWriter::new(open_cache_file(filename)?))
+/// Serializes entries as a JSON array of `[index, value]` arrays
+struct CompactEntries<'a>(&'a NodeMap);
+
+impl Serialize for CompactEntries<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_seq(self.0.iter().map(|v| (*v.key(), *v.value())))
+    }
+}
+
+const GZIP_MAGIC: [u8; 2] = [0x1f, 0x8b];
+
+/// Open file for reading, transparently decompressing it if it starts with the gzip magic header
+fn open_for_read<P: AsRef<Path>>(filename: P) -> OsmNodeCacheResult<Box<dyn Read>> {
+    let mut reader = BufReader::new(File::open(filename)?);
+    if reader.fill_buf()?.starts_with(&GZIP_MAGIC) {
+        Ok(Box::new(BufReader::new(GzDecoder::new(reader))))
+    } else {
+        Ok(Box::new(reader))
+    }
+}
+
+/// Hex dump of the first few bytes of a file, to help identify misrouted files in error messages
+fn header_hexdump<P: AsRef<Path>>(filename: P) -> String {
+    let mut header = Vec::new();
+    if let Ok(file) = File::open(filename) {
+        let _ = file.take(16).read_to_end(&mut header);
+    }
+    header
+        .iter()
+        .map(|b| format!("{b:02x}"))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Options used by `bincode::serialize_into` and `bincode::deserialize_from`
+fn legacy_bincode_options() -> impl Options {
+    bincode::DefaultOptions::new()
+        .with_fixint_encoding()
+        .allow_trailing_bytes()
+}
+
+fn write_varint<W: Write>(writer: &mut W, mut value: u64) -> std::io::Result<()> {
+    let mut buf = [0_u8; 10];
+    let mut len = 0;
+    while value >= 0x80 {
+        buf[len] = (value as u8) | 0x80;
+        value >>= 7;
+        len += 1;
+    }
+    buf[len] = value as u8;
+    writer.write_all(&buf[..=len])
+}
+
+fn read_varint<R: Read>(reader: &mut R) -> std::io::Result<u64> {
+    let mut value = 0_u64;
+    for shift in (0..64).step_by(7) {
+        let mut byte = [0_u8];
+        reader.read_exact(&mut byte)?;
+        value |= u64::from(byte[0] & 0x7f) << shift;
+        if byte[0] & 0x80 == 0 {
+            return Ok(value);
+        }
+    }
+    Err(std::io::Error::new(
+        ErrorKind::InvalidData,
+        "varint is too long",
+    ))
+}
+
+/// Write a file atomically: the content is written to a temporary file in the same directory,
+/// which is synced and renamed over the target only if writing succeeds. Readers never see a partial file,
+/// and an interrupted save leaves the previous file in place.
+fn save_atomically<P, F>(filename: P, write: F) -> OsmNodeCacheResult<()>
+where
+    P: AsRef<Path>,
+    F: FnOnce(&mut BufWriter<File>) -> OsmNodeCacheResult<()>,
+{
+    static SAVE_COUNTER: AtomicUsize = AtomicUsize::new(0);
+    let filename = filename.as_ref();
+    let name = filename.file_name().unwrap_or_default().to_string_lossy();
+    let temp = filename.with_file_name(format!(
+        ".{name}.{}.{}.tmp",
+        process::id(),
+        SAVE_COUNTER.fetch_add(1, Ordering::Relaxed)
+    ));
+    let file = File::create(&temp)
+        .map_err(|e| OsmNodeCacheError::InvalidCacheFile(filename.to_path_buf(), e))?;
+    let mut writer = BufWriter::new(file);
+    let result = write(&mut writer).and_then(|()| {
+        let file = writer.into_inner().map_err(IntoInnerError::into_error)?;
+        file.sync_all()?;
+        Ok(fs::rename(&temp, filename)?)
+    });
+    if result.is_err() {
+        let _ = fs::remove_file(&temp);
+    }
+    result
 }
 
 impl HashMapCache {
@@ -27,44 +153,442 @@ impl HashMapCache {
         Self::default()
     }
 
+    /// Same as [`get_accessor`](CacheStore::get_accessor), but returns the concrete type,
+    /// so that `get` and `set` can be inlined. The accessor shares the map with this cache.
+    #[must_use]
+    pub fn accessor(&self) -> Self {
+        self.clone()
+    }
+
     #[must_use]
     pub fn with_capacity(capacity: usize) -> Self {
         Self {
-            data: Arc::new(DashMap::with_capacity(capacity)),
+            data: Arc::new(NodeMap::with_capacity_and_hasher(capacity, FxBuildHasher)),
+            ..Self::default()
+        }
+    }
+
+    /// Create an empty cache with the given capacity, split into `shard_amount` independently locked shards.
+    /// More shards reduce lock contention between many writing threads.
+    /// Panics if `shard_amount` is not a power of two greater than one.
+    #[must_use]
+    pub fn with_shard_amount(capacity: usize, shard_amount: usize) -> Self {
+        Self {
+            data: Arc::new(NodeMap::with_capacity_and_hasher_and_shard_amount(
+                capacity,
+                FxBuildHasher,
+                shard_amount,
+            )),
+            ..Self::default()
+        }
+    }
+
+    /// Create an empty cache whose `get` returns `default` for missing values
+    #[must_use]
+    pub fn with_default(default: u64) -> Self {
+        Self {
+            default,
+            ..Self::default()
+        }
+    }
+
+    /// Value returned by `get` for missing values, see [`with_default`](Self::with_default)
+    pub(crate) fn default_value(&self) -> u64 {
+        self.default
+    }
+
+    /// Number of stored values
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.data.len()
+    }
+
+    /// Number of values the cache can hold without re-allocating, summed over all shards
+    #[must_use]
+    pub fn capacity(&self) -> usize {
+        self.data.capacity()
+    }
+
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.data.is_empty()
+    }
+
+    /// Get the value at index, or atomically compute and store it with `f` if it is missing.
+    /// When several threads miss the same index, `f` runs only once and all of them get its result.
+    /// Note that `f` runs while holding the shard lock, so it must not access this cache.
+    pub fn get_or_insert_with(&self, index: usize, f: impl FnOnce() -> u64) -> u64 {
+        *self.data.entry(index as u64).or_insert_with(f)
+    }
+
+    /// Count the values that decode to a point inside the `(min_lat, min_lon, max_lat, max_lon)` bounding box,
+    /// in the same format as [`bbox_of`](crate::geo_util::bbox_of), including the points on its edges.
+    /// The scan runs in parallel without collecting the values. A box crossing the antimeridian,
+    /// i.e. with `min_lon > max_lon`, contains no points.
+    #[must_use]
+    pub fn count_in_bbox(&self, bbox: (f64, f64, f64, f64)) -> usize {
+        let (min_lat, min_lon, max_lat, max_lon) = bbox;
+        self.data
+            .par_iter()
+            .filter(|v| {
+                let (lat, lon) = unpack_lat_lon(*v.value());
+                (min_lat..=max_lat).contains(&lat) && (min_lon..=max_lon).contains(&lon)
+            })
+            .count()
+    }
+
+    /// Count the values by their decoded latitude in `bins` equal-width bins spanning -90..=90,
+    /// e.g. to see how the nodes are distributed. The last bin includes 90.
+    /// The shards are scanned in parallel. Returns an empty histogram if `bins` is 0.
+    #[must_use]
+    pub fn lat_histogram(&self, bins: usize) -> Vec<usize> {
+        if bins == 0 {
+            return Vec::new();
+        }
+        let scale = bins as f64 / 180.0;
+        self.data
+            .par_iter()
+            .fold(
+                || vec![0; bins],
+                |mut counts, v| {
+                    let (lat, _) = unpack_lat_lon(*v.value());
+                    // Casting saturates at 0 for the values that decode slightly below -90
+                    let bin = (((lat + 90.0) * scale) as usize).min(bins - 1);
+                    counts[bin] += 1;
+                    counts
+                },
+            )
+            .reduce(
+                || vec![0; bins],
+                |mut total, counts| {
+                    for (t, c) in total.iter_mut().zip(counts) {
+                        *t += c;
+                    }
+                    total
+                },
+            )
+    }
+
+    /// Create a new cache with `f` applied to each stored value, e.g. to re-encode coordinates.
+    /// The values are transformed in parallel, and the default value is kept as is.
+    #[must_use]
+    pub fn map_values<F: Fn(u64) -> u64 + Sync + Send>(&self, f: F) -> HashMapCache {
+        Self {
+            data: Arc::new(
+                self.data
+                    .par_iter()
+                    .map(|v| (*v.key(), f(*v.value())))
+                    .collect(),
+            ),
+            default: self.default,
+        }
+    }
+
+    /// All stored `(index, value)` pairs, sorted by index.
+    /// This collects a copy of the whole cache, using 16 bytes per entry on top of the cache itself.
+    #[must_use]
+    pub fn iter_sorted(&self) -> Vec<(u64, u64)> {
+        let mut entries: Vec<_> = self.data.iter().map(|v| (*v.key(), *v.value())).collect();
+        entries.sort_unstable_by_key(|(k, _)| *k);
+        entries
+    }
+
+    /// Consume the cache into its `(index, value)` pairs, sorted by index.
+    /// If this is the only clone of the cache, the entries are moved out of the map,
+    /// otherwise they are copied, same as [`iter_sorted`](Self::iter_sorted).
+    #[must_use]
+    pub fn into_sorted_vec(self) -> Vec<(u64, u64)> {
+        match Arc::try_unwrap(self.data) {
+            Ok(data) => {
+                let mut entries: Vec<_> = data.into_iter().collect();
+                entries.sort_unstable_by_key(|(k, _)| *k);
+                entries
+            }
+            Err(data) => Self { data, ..self }.iter_sorted(),
         }
     }
 
+    /// Remove all values with an index in `range`, e.g. to release a block of ids that is no longer needed,
+    /// and return how many were removed. Small ranges are removed key by key, otherwise all entries are scanned.
+    pub fn remove_range(&self, range: Range<u64>) -> usize {
+        let span = range.end.saturating_sub(range.start);
+        if span <= self.data.len() as u64 {
+            range.filter(|k| self.data.remove(k).is_some()).count()
+        } else {
+            let mut removed = 0;
+            self.data.retain(|k, _| {
+                let keep = !range.contains(k);
+                if !keep {
+                    removed += 1;
+                }
+                keep
+            });
+            removed
+        }
+    }
+
+    /// Reserve space for at least `additional` more values, e.g. before a large import, to avoid
+    /// re-hashing while inserting. The space is split evenly between the shards, so a shard receiving
+    /// more than its share of the new values may still grow.
+    pub fn reserve(&self, additional: usize) {
+        let shards = self.data.shards();
+        let per_shard = (additional + shards.len() - 1) / shards.len();
+        for shard in shards {
+            shard.write().reserve(per_shard);
+        }
+    }
+
+    /// Insert all `(index, value)` pairs using the rayon thread pool, e.g. a precomputed list of nodes.
+    /// The shards are locked independently, so this scales with the number of threads.
+    /// If an index is given more than once, it is unspecified which of its values is kept.
+    pub fn par_extend<I: IntoParallelIterator<Item = (u64, u64)>>(&self, items: I) {
+        items.into_par_iter().for_each(|(index, value)| {
+            self.data.insert(index, value);
+        });
+    }
+
+    /// Add all values from `other` into this cache, using `policy` for indexes present in both.
+    /// Each shard of `other` is copied out before inserting its values, so that no lock of `other` is held
+    /// while locking the shards of this cache, allowing concurrent merges in both directions.
+    pub fn merge(&self, other: &HashMapCache, policy: ConflictPolicy) {
+        if Arc::ptr_eq(&self.data, &other.data) {
+            // Merging a cache with itself or its own clone is a no-op
+            return;
+        }
+        for shard in other.data.shards() {
+            let items: Vec<(u64, u64)> = shard.read().iter().map(|(k, v)| (*k, *v.get())).collect();
+            for (key, value) in items {
+                self.merge_value(key, value, policy);
+            }
+        }
+    }
+
+    fn merge_value(&self, key: u64, value: u64, policy: ConflictPolicy) {
+        self.data
+            .entry(key)
+            .and_modify(|v| {
+                *v = match policy {
+                    ConflictPolicy::KeepSelf => *v,
+                    ConflictPolicy::KeepOther => value,
+                    ConflictPolicy::Max => (*v).max(value),
+                    ConflictPolicy::Min => (*v).min(value),
+                }
+            })
+            .or_insert(value);
+    }
+
     pub fn from_json<P: AsRef<Path>>(filename: P) -> OsmNodeCacheResult<Self> {
         Ok(Self {
             data: Arc::new(serde_json::from_reader(open_for_read(filename)?)?),
+            ..Self::default()
+        })
+    }
+
+    /// Load cache saved with [`save_as_json_compact`](Self::save_as_json_compact)
+    pub fn from_json_compact<P: AsRef<Path>>(filename: P) -> OsmNodeCacheResult<Self> {
+        let entries: Vec<(u64, u64)> = serde_json::from_reader(open_for_read(filename)?)?;
+        Ok(Self {
+            data: Arc::new(entries.into_iter().collect()),
+            ..Self::default()
         })
     }
 
+    /// Load cache from a bincode file written by [`save_as_bin`](Self::save_as_bin).
+    /// If the content cannot be decoded, the error includes the file path and a hex dump of its first bytes.
     pub fn from_bin<P: AsRef<Path>>(filename: P) -> OsmNodeCacheResult<Self> {
+        Self::from_bin_with(filename, legacy_bincode_options())
+    }
+
+    /// Load cache from a reader in the format of [`save_as_bin`](Self::save_as_bin),
+    /// e.g. an in-memory `Cursor` in tests. Unlike [`from_bin`](Self::from_bin), gzip is not detected.
+    ///
+    /// ```
+    /// use std::io::Cursor;
+    /// use osmnodecache::{Cache, HashMapCache};
+    ///
+    /// let mut cache = HashMapCache::new();
+    /// cache.set(42, 420);
+    /// let mut buffer = Cursor::new(Vec::new());
+    /// cache.save_as_bin_writer(&mut buffer).unwrap();
+    /// buffer.set_position(0);
+    /// let loaded = HashMapCache::from_bin_reader(buffer).unwrap();
+    /// assert_eq!(loaded.get(42), 420);
+    /// ```
+    pub fn from_bin_reader<R: Read>(reader: R) -> OsmNodeCacheResult<Self> {
+        Ok(Self {
+            data: Arc::new(legacy_bincode_options().deserialize_from(reader)?),
+            ..Self::default()
+        })
+    }
+
+    /// Load cache from a bincode file written with the given options,
+    /// see [`save_as_bin_with`](Self::save_as_bin_with).
+    pub fn from_bin_with<P: AsRef<Path>, O: Options>(
+        filename: P,
+        options: O,
+    ) -> OsmNodeCacheResult<Self> {
+        let filename = filename.as_ref();
+        let data = options
+            .deserialize_from(open_for_read(filename)?)
+            .map_err(|e| {
+                let msg = format!("{e}, file starts with [{}]", header_hexdump(filename));
+                OsmNodeCacheError::InvalidCacheFile(
+                    filename.to_path_buf(),
+                    std::io::Error::new(ErrorKind::InvalidData, msg),
+                )
+            })?;
         Ok(Self {
-            data: Arc::new(bincode::deserialize_from(open_for_read(filename)?)?),
+            data: Arc::new(data),
+            ..Self::default()
         })
     }
 
+    /// Save cache as a JSON object. Same as all other `save_as_*` methods, the file is replaced
+    /// atomically via a temporary file in the same directory, so it is never left partially written.
     pub fn save_as_json<P: AsRef<Path>>(&self, filename: P) -> OsmNodeCacheResult<()> {
-        Ok(serde_json::to_writer(
-            open_for_write(filename)?,
-            self.data.as_ref(),
-        )?)
+        save_atomically(filename, |w| {
+            Ok(serde_json::to_writer(w, self.data.as_ref())?)
+        })
+    }
+
+    /// Save cache as a compact JSON array of `[index, value]` arrays in no particular order,
+    /// e.g. `[[1,10],[2,20]]`. Unlike the object form used by [`save_as_json`](Self::save_as_json),
+    /// ids are stored as numbers rather than strings, so they are parsed without an extra string conversion.
+    /// Use [`from_json_compact`](Self::from_json_compact) to load it.
+    pub fn save_as_json_compact<P: AsRef<Path>>(&self, filename: P) -> OsmNodeCacheResult<()> {
+        save_atomically(filename, |w| {
+            Ok(serde_json::to_writer(
+                w,
+                &CompactEntries(self.data.as_ref()),
+            )?)
+        })
+    }
+
+    pub fn save_as_json_gz<P: AsRef<Path>>(&self, filename: P) -> OsmNodeCacheResult<()> {
+        save_atomically(filename, |w| {
+            let mut encoder = GzEncoder::new(w, Compression::default());
+            serde_json::to_writer(&mut encoder, self.data.as_ref())?;
+            encoder.finish()?;
+            Ok(())
+        })
     }
 
     pub fn save_as_pretty_json<P: AsRef<Path>>(&self, filename: P) -> OsmNodeCacheResult<()> {
-        Ok(serde_json::to_writer_pretty(
-            open_for_write(filename)?,
-            self.data.as_ref(),
-        )?)
+        save_atomically(filename, |w| {
+            Ok(serde_json::to_writer_pretty(w, self.data.as_ref())?)
+        })
+    }
+
+    /// Same as [`save_as_json`](Self::save_as_json), but with the entries sorted by index,
+    /// so that saving the same content always produces the same file.
+    /// Sorting requires a temporary copy of all entries, see [`iter_sorted`](Self::iter_sorted).
+    pub fn save_as_sorted_json<P: AsRef<Path>>(&self, filename: P) -> OsmNodeCacheResult<()> {
+        save_atomically(filename, |w| {
+            Ok(serde_json::to_writer(
+                w,
+                &OrderedEntries(&self.iter_sorted()),
+            )?)
+        })
+    }
+
+    /// Same as [`save_as_pretty_json`](Self::save_as_pretty_json), but with the entries sorted by index.
+    pub fn save_as_sorted_pretty_json<P: AsRef<Path>>(
+        &self,
+        filename: P,
+    ) -> OsmNodeCacheResult<()> {
+        save_atomically(filename, |w| {
+            Ok(serde_json::to_writer_pretty(
+                w,
+                &OrderedEntries(&self.iter_sorted()),
+            )?)
+        })
+    }
+
+    /// Save cache in a compact delta format similar to the PBF `DenseNodes`: the number of entries,
+    /// followed by the entries sorted by index. Each entry is stored as zigzag varint deltas from the
+    /// previous entry of the index, and of the two i32 halves of the value, i.e. latitude and longitude
+    /// as stored by [`Cache::set_lat_lon`]. Nearby nodes with sequential ids often take just 3-5 bytes.
+    /// Sorting requires a temporary copy of all entries, see [`iter_sorted`](Self::iter_sorted).
+    pub fn save_as_delta_bin<P: AsRef<Path>>(&self, filename: P) -> OsmNodeCacheResult<()> {
+        let entries = self.iter_sorted();
+        save_atomically(filename, |writer| {
+            write_varint(writer, entries.len() as u64)?;
+            let (mut prev_index, mut prev_lat, mut prev_lon) = (0_u64, 0_i32, 0_i32);
+            for (index, value) in entries {
+                let (lat, lon) = unpack_i32s(value);
+                write_varint(writer, zigzag_encode(index.wrapping_sub(prev_index) as i64))?;
+                write_varint(writer, zigzag_encode(i64::from(lat) - i64::from(prev_lat)))?;
+                write_varint(writer, zigzag_encode(i64::from(lon) - i64::from(prev_lon)))?;
+                (prev_index, prev_lat, prev_lon) = (index, lat, lon);
+            }
+            Ok(())
+        })
+    }
+
+    /// Load cache saved with [`save_as_delta_bin`](Self::save_as_delta_bin)
+    pub fn from_delta_bin<P: AsRef<Path>>(filename: P) -> OsmNodeCacheResult<Self> {
+        let filename = filename.as_ref();
+        let to_err =
+            |e: std::io::Error| OsmNodeCacheError::InvalidCacheFile(filename.to_path_buf(), e);
+        let mut reader = open_for_read(filename)?;
+        let count = read_varint(&mut reader).map_err(to_err)?;
+        let data = NodeMap::default();
+        let (mut index, mut lat, mut lon) = (0_u64, 0_i32, 0_i32);
+        for _ in 0..count {
+            index =
+                index.wrapping_add(zigzag_decode(read_varint(&mut reader).map_err(to_err)?) as u64);
+            lat =
+                (i64::from(lat) + zigzag_decode(read_varint(&mut reader).map_err(to_err)?)) as i32;
+            lon =
+                (i64::from(lon) + zigzag_decode(read_varint(&mut reader).map_err(to_err)?)) as i32;
+            data.insert(index, pack_i32s(lat, lon));
+        }
+        Ok(Self {
+            data: Arc::new(data),
+            ..Self::default()
+        })
     }
 
+    /// Save cache with the default bincode configuration:
+    /// little-endian, fixed-size integers, same as `bincode::serialize`.
     pub fn save_as_bin<P: AsRef<Path>>(&self, filename: P) -> OsmNodeCacheResult<()> {
-        Ok(bincode::serialize_into(
-            open_for_write(filename)?,
-            self.data.as_ref(),
-        )?)
+        self.save_as_bin_with(filename, legacy_bincode_options())
+    }
+
+    /// Write cache to a writer in the format of [`save_as_bin`](Self::save_as_bin),
+    /// e.g. an in-memory `Cursor`, see [`from_bin_reader`](Self::from_bin_reader).
+    pub fn save_as_bin_writer<W: Write>(&self, writer: W) -> OsmNodeCacheResult<()> {
+        Ok(legacy_bincode_options().serialize_into(writer, self.data.as_ref())?)
+    }
+
+    /// Save cache with custom bincode options, e.g. `bincode::DefaultOptions::new()` uses
+    /// variable-length integers, producing much smaller files when ids and values are small.
+    /// The file must be loaded with [`from_bin_with`](Self::from_bin_with) using the same options.
+    pub fn save_as_bin_with<P: AsRef<Path>, O: Options>(
+        &self,
+        filename: P,
+        options: O,
+    ) -> OsmNodeCacheResult<()> {
+        save_atomically(filename, |w| {
+            Ok(options.serialize_into(w, self.data.as_ref())?)
+        })
+    }
+}
+
+/// A short summary for status output, e.g. `HashMapCache(1.2M entries, capacity 1.8M, ~30MB)`.
+/// The memory use is estimated from the allocated capacity, excluding the allocator overhead.
+impl fmt::Display for HashMapCache {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // Each slot holds the key and the value, plus one hashbrown control byte
+        let slot_size = 2 * size_of::<u64>() + 1;
+        let capacity = self.capacity();
+        write!(
+            f,
+            "HashMapCache({} entries, capacity {}, ~{})",
+            format_count(self.len() as u64),
+            format_count(capacity as u64),
+            format_bytes((capacity * slot_size) as u64)
+        )
     }
 }
 
@@ -76,7 +600,11 @@ impl CacheStore for HashMapCache {
 
 impl Cache for HashMapCache {
     fn get(&self, index: usize) -> u64 {
-        self.data.get(&(index as u64)).map_or(0_u64, |v| *v.value())
+        self.try_get(index).unwrap_or(self.default)
+    }
+
+    fn try_get(&self, index: usize) -> Option<u64> {
+        self.data.get(&(index as u64)).map(|v| *v.value())
     }
 
     fn set(&mut self, index: usize, value: u64) {
@@ -87,13 +615,17 @@ impl Cache for HashMapCache {
 #[cfg(test)]
 mod tests {
     use std::fs;
+    use std::io::Cursor;
     use std::path::Path;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::thread;
 
-    use rayon::iter::{ParallelBridge, ParallelIterator};
+    use rayon::iter::{IntoParallelIterator, ParallelBridge, ParallelIterator};
 
-    use crate::hashmap::HashMapCache;
+    use crate::hashmap::{ConflictPolicy, HashMapCache};
     use crate::traits::tests::get_random_items;
-    use crate::traits::Cache;
+    use crate::traits::{Cache, NodeId};
+    use crate::OsmNodeCacheError;
 
     #[test]
     fn hashmap_test() {
@@ -138,6 +670,98 @@ mod tests {
         cleanup_test_file(filename);
     }
 
+    #[test]
+    fn hashmap_file_json_sorted_test() {
+        let filename = Path::new("./hashmap_test.sorted.json");
+        let mut cache = HashMapCache::new();
+        for i in [30_usize, 2, 100, 1] {
+            cache.set(i, i as u64 * 10);
+        }
+        assert_eq!(
+            cache.iter_sorted(),
+            vec![(1, 10), (2, 20), (30, 300), (100, 1000)]
+        );
+        let _ = fs::remove_file(filename);
+        cache.save_as_sorted_json(filename).unwrap();
+        assert_eq!(
+            fs::read_to_string(filename).unwrap(),
+            r#"{"1":10,"2":20,"30":300,"100":1000}"#
+        );
+        let loaded = HashMapCache::from_json(filename).unwrap();
+        assert_eq!(loaded.iter_sorted(), cache.iter_sorted());
+        cleanup_test_file(filename);
+    }
+
+    #[test]
+    fn into_sorted_vec_test() {
+        let mut cache = HashMapCache::new();
+        for i in [30_usize, 2, 100, 1] {
+            cache.set(i, i as u64 * 10);
+        }
+        let expected = vec![(1, 10), (2, 20), (30, 300), (100, 1000)];
+        let shared = cache.clone();
+        assert_eq!(shared.into_sorted_vec(), expected);
+        assert_eq!(cache.len(), 4);
+        assert_eq!(cache.into_sorted_vec(), expected);
+    }
+
+    #[test]
+    fn hashmap_file_json_compact_test() {
+        let items = 100000;
+        let filename = Path::new("./hashmap_test.compact.json");
+        let cache = new_hashmap(items);
+        let _ = fs::remove_file(filename);
+        cache.save_as_json_compact(filename).unwrap();
+        test_values(&HashMapCache::from_json_compact(filename).unwrap(), items);
+
+        let mut small = HashMapCache::new();
+        small.set(7, 70);
+        small.save_as_json_compact(filename).unwrap();
+        assert_eq!(fs::read_to_string(filename).unwrap(), "[[7,70]]");
+        cleanup_test_file(filename);
+    }
+
+    #[test]
+    fn hashmap_file_atomic_save_test() {
+        let temp_files = || {
+            fs::read_dir(".")
+                .unwrap()
+                .filter(|e| {
+                    let name = e.as_ref().unwrap().file_name();
+                    name.to_string_lossy().starts_with(".hashmap_test.atomic")
+                })
+                .count()
+        };
+        let filename = Path::new("./hashmap_test.atomic.json");
+        let mut cache = new_hashmap(10);
+        cache.save_as_json(filename).unwrap();
+        cache.set(20, 20);
+        cache.save_as_json(filename).unwrap();
+        assert_eq!(HashMapCache::from_json(filename).unwrap().len(), 11);
+        assert_eq!(temp_files(), 0);
+        cleanup_test_file(filename);
+
+        // The target cannot be replaced, so the save fails without leaving a temporary file
+        let dir = Path::new("./hashmap_test.atomic_dir");
+        let _ = fs::create_dir(dir);
+        assert!(cache.save_as_bin(dir).is_err());
+        assert!(dir.is_dir());
+        assert_eq!(temp_files(), 0);
+        fs::remove_dir(dir).unwrap();
+    }
+
+    #[test]
+    fn hashmap_file_json_gz_test() {
+        let items = 100000;
+        let filename = Path::new("./hashmap_test.json.gz");
+        let cache = new_hashmap(items);
+        let _ = fs::remove_file(filename);
+        cache.save_as_json_gz(filename).unwrap();
+        assert_eq!(&fs::read(filename).unwrap()[..2], &[0x1f, 0x8b]);
+        test_values(&HashMapCache::from_json(filename).unwrap(), items);
+        cleanup_test_file(filename);
+    }
+
     #[test]
     fn hashmap_file_bin_test() {
         let items = 100000;
@@ -146,6 +770,90 @@ mod tests {
         let _ = fs::remove_file(filename);
         cache.save_as_bin(filename).unwrap();
         test_values(&HashMapCache::from_bin(filename).unwrap(), items);
+
+        // In-memory buffers use the same format as files
+        let data = fs::read(filename).unwrap();
+        test_values(
+            &HashMapCache::from_bin_reader(Cursor::new(&data)).unwrap(),
+            items,
+        );
+        let mut buffer = Vec::new();
+        cache.save_as_bin_writer(&mut buffer).unwrap();
+        assert_eq!(buffer.len(), data.len());
+        cleanup_test_file(filename);
+    }
+
+    #[test]
+    fn hashmap_file_bin_varint_test() {
+        let items = 100000;
+        let fixint = Path::new("./hashmap_test.fixint.bin");
+        let varint = Path::new("./hashmap_test.varint.bin");
+        let cache = new_hashmap(items);
+        let _ = fs::remove_file(fixint);
+        let _ = fs::remove_file(varint);
+        let options = bincode::DefaultOptions::new();
+        cache.save_as_bin(fixint).unwrap();
+        cache.save_as_bin_with(varint, options).unwrap();
+        let fixint_size = fs::metadata(fixint).unwrap().len();
+        let varint_size = fs::metadata(varint).unwrap().len();
+        assert!(
+            varint_size * 2 < fixint_size,
+            "varint={varint_size} fixint={fixint_size}"
+        );
+        test_values(
+            &HashMapCache::from_bin_with(varint, options).unwrap(),
+            items,
+        );
+        cleanup_test_file(fixint);
+        cleanup_test_file(varint);
+    }
+
+    #[test]
+    fn hashmap_file_delta_bin_test() {
+        let items = 100000;
+        let plain = Path::new("./hashmap_test.plain.bin");
+        let delta = Path::new("./hashmap_test.delta.bin");
+        let mut cache = HashMapCache::new();
+        for i in 0..items {
+            let step = i as f64 * 0.0001;
+            cache.set_lat_lon(1_000_000 + i * 3, 45.0 + step, -120.0 - step);
+        }
+        cache.set(0, u64::MAX);
+        cache.set(usize::MAX, 1);
+        cache.set_lat_lon(5, -90.0, 180.0);
+        let _ = fs::remove_file(plain);
+        let _ = fs::remove_file(delta);
+        cache.save_as_bin(plain).unwrap();
+        cache.save_as_delta_bin(delta).unwrap();
+        let plain_size = fs::metadata(plain).unwrap().len();
+        let delta_size = fs::metadata(delta).unwrap().len();
+        assert!(
+            delta_size * 3 < plain_size,
+            "delta={delta_size} plain={plain_size}"
+        );
+        let loaded = HashMapCache::from_delta_bin(delta).unwrap();
+        assert_eq!(loaded.iter_sorted(), cache.iter_sorted());
+
+        // Truncated file
+        let data = fs::read(delta).unwrap();
+        fs::write(delta, &data[..data.len() - 1]).unwrap();
+        assert!(matches!(
+            HashMapCache::from_delta_bin(delta),
+            Err(OsmNodeCacheError::InvalidCacheFile(..))
+        ));
+        cleanup_test_file(plain);
+        cleanup_test_file(delta);
+    }
+
+    #[test]
+    fn hashmap_file_bin_invalid_test() {
+        let filename = Path::new("./hashmap_test.invalid.bin");
+        fs::write(filename, b"{\"1\":2}").unwrap();
+        let err = HashMapCache::from_bin(filename).err().unwrap();
+        assert!(matches!(err, OsmNodeCacheError::InvalidCacheFile(..)));
+        let msg = err.to_string();
+        assert!(msg.contains("hashmap_test.invalid.bin"), "{msg}");
+        assert!(msg.contains("[7b 22 31 22 3a 32 7d]"), "{msg}");
         cleanup_test_file(filename);
     }
 
@@ -178,4 +886,256 @@ mod tests {
         assert_eq!(cache.get(11), 42);
         assert_eq!(clone.get(12), 43);
     }
+
+    #[test]
+    fn shard_amount_test() {
+        let mut cache = HashMapCache::with_shard_amount(100, 128);
+        cache.set(1, 2);
+        assert_eq!(cache.get(1), 2);
+    }
+
+    #[test]
+    #[should_panic]
+    fn shard_amount_invalid_test() {
+        let _ = HashMapCache::with_shard_amount(100, 3);
+    }
+
+    #[test]
+    fn len_test() {
+        let mut cache = HashMapCache::new();
+        assert!(cache.is_empty());
+        assert_eq!(cache.len(), 0);
+        cache.set(1, 2);
+        cache.set(1, 3);
+        cache.set(5, 0);
+        assert!(!cache.is_empty());
+        assert_eq!(cache.len(), 2);
+    }
+
+    #[test]
+    fn reserve_test() {
+        let mut cache = HashMapCache::new();
+        cache.reserve(100_000);
+        let capacity = cache.capacity();
+        assert!(capacity >= 100_000);
+        for i in 0..50_000 {
+            cache.set(i, i as u64);
+        }
+        assert_eq!(cache.capacity(), capacity);
+        assert_eq!(cache.len(), 50_000);
+        cache.reserve(0);
+        assert_eq!(cache.capacity(), capacity);
+    }
+
+    #[test]
+    fn par_extend_test() {
+        let cache = HashMapCache::new();
+        let items: Vec<_> = (0..10_000_u64).map(|i| (i * 3, i + 1)).collect();
+        cache.par_extend(items);
+        cache.par_extend((0..5_u64).into_par_iter().map(|i| (i * 3, 0)));
+        assert_eq!(cache.len(), 10_000);
+        assert_eq!(cache.get(0), 0);
+        assert_eq!(cache.get(15), 6);
+        assert_eq!(cache.get(29_997), 10_000);
+    }
+
+    #[test]
+    fn accessor_test() {
+        let cache = HashMapCache::new();
+        let mut accessor = cache.accessor();
+        accessor.set(5, 50);
+        assert_eq!(cache.get(5), 50);
+        assert_eq!(accessor.get(5), 50);
+    }
+
+    #[test]
+    fn display_test() {
+        let cache = HashMapCache::new();
+        assert_eq!(
+            cache.to_string(),
+            "HashMapCache(0 entries, capacity 0, ~0B)"
+        );
+        let mut cache = HashMapCache::new();
+        for i in 0..1500 {
+            cache.set(i, 1);
+        }
+        let summary = cache.to_string();
+        assert!(
+            summary.starts_with("HashMapCache(1.5K entries, capacity "),
+            "{summary}"
+        );
+        assert!(summary.ends_with("KB)"), "{summary}");
+    }
+
+    #[test]
+    fn merge_test() {
+        let merged = |policy| {
+            let mut cache = HashMapCache::new();
+            cache.set(1, 10);
+            cache.set(2, 20);
+            let mut other = HashMapCache::new();
+            other.set(2, 5);
+            other.set(3, 30);
+            cache.merge(&other, policy);
+            assert_eq!(cache.len(), 3);
+            assert_eq!(other.len(), 2);
+            assert_eq!(cache.get(1), 10);
+            assert_eq!(cache.get(3), 30);
+            cache.get(2)
+        };
+        assert_eq!(merged(ConflictPolicy::KeepSelf), 20);
+        assert_eq!(merged(ConflictPolicy::KeepOther), 5);
+        assert_eq!(merged(ConflictPolicy::Max), 20);
+        assert_eq!(merged(ConflictPolicy::Min), 5);
+
+        let cache = new_hashmap(10);
+        cache.merge(&cache.clone(), ConflictPolicy::KeepOther);
+        cache.merge(&cache, ConflictPolicy::Max);
+        test_values(&cache, 10);
+    }
+
+    #[test]
+    fn merge_both_ways() {
+        let a = new_hashmap(1000);
+        let b = HashMapCache::new();
+        b.par_extend((1000..2000).into_par_iter().map(|v| (v, v)));
+        let (a2, b2) = (a.clone(), b.clone());
+        let t = thread::spawn(move || {
+            for _ in 0..10 {
+                a2.merge(&b2, ConflictPolicy::KeepSelf);
+            }
+        });
+        for _ in 0..10 {
+            b.merge(&a, ConflictPolicy::KeepSelf);
+        }
+        t.join().unwrap();
+        assert_eq!(a.len(), 2000);
+        assert_eq!(b.len(), 2000);
+    }
+
+    #[test]
+    fn node_id_test() {
+        let mut cache = HashMapCache::new();
+        cache.set_node(NodeId(7), 42);
+        assert_eq!(cache.get_node(NodeId(7)), 42);
+        assert_eq!(cache.get(7), 42);
+    }
+
+    #[test]
+    fn get_range_test() {
+        let mut cache = HashMapCache::with_default(7);
+        cache.set(2, 20);
+        cache.set_lat_lon(3, 10.0, 20.0);
+        assert_eq!(cache.get_range(1..3), vec![7, 20]);
+        let coords = cache.get_range_lat_lon(3..4);
+        assert!((coords[0].0 - 10.0).abs() < 1e-6 && (coords[0].1 - 20.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn set_if_changed_test() {
+        let mut cache = HashMapCache::new();
+        assert!(cache.set_if_changed(1, 10));
+        assert!(!cache.set_if_changed(1, 10));
+        assert!(cache.set_if_changed(1, 20));
+        assert_eq!(cache.get(1), 20);
+        let mut boxed: Box<dyn Cache> = Box::new(cache.clone());
+        assert!(!boxed.set_if_changed(1, 20));
+    }
+
+    #[test]
+    fn pair_test() {
+        let mut cache = HashMapCache::new();
+        cache.set_pair(3, 7, u32::MAX);
+        assert_eq!(cache.get_pair(3), (7, u32::MAX));
+        assert_eq!(cache.get_pair(4), (0, 0));
+    }
+
+    #[test]
+    fn get_or_insert_with_test() {
+        let cache = HashMapCache::new();
+        let calls = AtomicUsize::new(0);
+        (0..800).into_par_iter().for_each(|i| {
+            let i = i % 100;
+            let value = cache.get_or_insert_with(i, || {
+                calls.fetch_add(1, Ordering::Relaxed);
+                i as u64 * 2
+            });
+            assert_eq!(value, i as u64 * 2);
+        });
+        assert_eq!(calls.load(Ordering::Relaxed), 100);
+        assert_eq!(cache.get_or_insert_with(5, || 1), 10);
+        assert_eq!(cache.len(), 100);
+    }
+
+    #[test]
+    fn count_in_bbox_test() {
+        let mut cache = HashMapCache::new();
+        cache.set_lat_lon(1, 10.0, 20.0);
+        cache.set_lat_lon(2, 10.5, 20.5);
+        cache.set_lat_lon(3, -10.0, 20.0);
+        cache.set_lat_lon(4, 10.0, 179.0);
+        assert_eq!(cache.count_in_bbox((9.0, 19.0, 11.0, 21.0)), 2);
+        assert_eq!(cache.count_in_bbox((-90.0, -180.0, 90.0, 180.0)), 4);
+        assert_eq!(cache.count_in_bbox((0.0, 0.0, 1.0, 1.0)), 0);
+        assert_eq!(
+            HashMapCache::new().count_in_bbox((-90.0, -180.0, 90.0, 180.0)),
+            0
+        );
+    }
+
+    #[test]
+    fn remove_range_test() {
+        let mut cache = HashMapCache::new();
+        for i in 0..1000 {
+            cache.set(i, i as u64 + 1);
+        }
+        assert_eq!(cache.remove_range(100..200), 100);
+        assert_eq!(cache.len(), 900);
+        assert_eq!(cache.try_get(150), None);
+        assert_eq!(cache.try_get(200), Some(201));
+        // A range larger than the cache is scanned instead
+        assert_eq!(cache.remove_range(900..1_000_000), 100);
+        assert_eq!(cache.remove_range(0..0), 0);
+        assert_eq!(cache.len(), 800);
+        assert_eq!(cache.try_get(899), Some(900));
+    }
+
+    #[test]
+    fn lat_histogram_test() {
+        let mut cache = HashMapCache::new();
+        cache.set_lat_lon(1, -90.0, 0.0);
+        cache.set_lat_lon(2, -45.0, 0.0);
+        cache.set_lat_lon(3, 10.0, 20.0);
+        cache.set_lat_lon(4, 80.0, 20.0);
+        cache.set_lat_lon(5, 90.0, 20.0);
+        cache.set_lat_lon_i32(6, i32::MIN, 0);
+        assert_eq!(cache.lat_histogram(4), vec![2, 1, 1, 2]);
+        assert_eq!(cache.lat_histogram(1), vec![6]);
+        assert!(cache.lat_histogram(0).is_empty());
+        assert_eq!(HashMapCache::new().lat_histogram(2), vec![0, 0]);
+    }
+
+    #[test]
+    fn map_values_test() {
+        let mut cache = HashMapCache::with_default(7);
+        for i in 0..1000 {
+            cache.set(i, i as u64);
+        }
+        let doubled = cache.map_values(|v| v * 2);
+        assert_eq!(doubled.len(), 1000);
+        assert!((0..1000).all(|i| doubled.get(i) == i as u64 * 2));
+        assert_eq!(doubled.get(5000), 7);
+        assert_eq!(cache.get(10), 10);
+    }
+
+    #[test]
+    fn default_test() {
+        let mut cache = HashMapCache::with_default(u64::MAX);
+        cache.set(1, 0);
+        assert_eq!(cache.get(1), 0);
+        assert_eq!(cache.get(2), u64::MAX);
+        assert_eq!(cache.try_get(1), Some(0));
+        assert_eq!(cache.try_get(2), None);
+        assert_eq!(HashMapCache::new().get(2), 0);
+    }
 }
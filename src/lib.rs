@@ -1,31 +1,122 @@
 #![doc = include_str!("../README.md")]
+#![cfg_attr(not(feature = "std"), no_std)]
 
+#[cfg(feature = "std")]
 use std::path::PathBuf;
+#[cfg(feature = "std")]
+use std::sync::PoisonError;
 
+#[cfg(feature = "std")]
 use thiserror::Error;
 
-#[cfg(unix)]
+#[cfg(feature = "std")]
+pub use crate::buffered::BufferedDenseCache;
+#[cfg(feature = "std")]
+pub use crate::bytes::BytesCache;
+#[cfg(feature = "std")]
+pub use crate::dense128::{Cache128Accessor, DenseFileCache128};
+#[cfg(all(unix, feature = "std"))]
 pub use crate::dense_file::Advice;
-pub use crate::dense_file::{DenseFileCache, DenseFileCacheOpts};
-pub use crate::hashmap::HashMapCache;
+#[cfg(feature = "std")]
+pub use crate::dense_file::{
+    validate_page_size, CacheReader, CacheView, CacheWriter, DenseFileCache, DenseFileCacheOpts,
+    DenseFileStats, Endian, SizeRounding, CHECKSUM_ALGORITHM,
+};
+#[cfg(feature = "std")]
+pub use crate::hashmap::{ConflictPolicy, HashMapCache};
+#[cfg(feature = "std")]
+pub use crate::mapped::MappedCache;
+#[cfg(feature = "metrics")]
+pub use crate::metrics::CountingCache;
+#[cfg(feature = "osmpbf")]
+pub use crate::pbf::{import_nodes_from_pbf, PbfImportStats};
+#[cfg(feature = "std")]
+pub use crate::sharded::ShardedDenseCache;
+#[cfg(feature = "std")]
+pub use crate::slice::SliceCache;
+#[cfg(feature = "std")]
+pub use crate::tiered::{OverlayCache, TieredCache};
 
+#[cfg(feature = "mmap-async")]
+mod async_flush;
+#[cfg(feature = "std")]
+mod buffered;
+#[cfg(feature = "std")]
+mod bytes;
+#[cfg(feature = "std")]
+mod dense128;
+#[cfg(feature = "std")]
 mod dense_file;
+pub mod encoding;
+#[cfg(feature = "std")]
+pub mod geo_util;
+#[cfg(feature = "std")]
+mod hasher;
+#[cfg(feature = "std")]
 mod hashmap;
+#[cfg(feature = "std")]
+mod mapped;
+#[cfg(feature = "metrics")]
+mod metrics;
+#[cfg(feature = "osmpbf")]
+mod pbf;
+#[cfg(feature = "std")]
+mod sharded;
+#[cfg(feature = "std")]
+mod slice;
+#[cfg(feature = "test_util")]
+pub mod test_util;
+#[cfg(feature = "std")]
+mod tiered;
+#[cfg(feature = "std")]
 mod traits;
 
-pub use traits::{Cache, CacheStore};
+#[cfg(feature = "std")]
+pub use traits::{Cache, CacheStore, NodeId};
 
+#[cfg(feature = "std")]
 #[derive(Error, Debug)]
 pub enum OsmNodeCacheError {
     #[error("Invalid cache file {}: {1}", .0.to_string_lossy())]
     InvalidCacheFile(PathBuf, std::io::Error),
 
-    #[error("Invalid cache page size: page_size={page_size} is not a multiple of {element_size}.")]
+    #[error("Invalid cache page size: page_size={page_size} is not a positive multiple of {element_size}.")]
     InvalidPageSize {
         page_size: usize,
         element_size: usize,
     },
 
+    #[error("Failed to map {size} bytes for {}: {source}. Consider lowering init_size or page_size.", .filename.to_string_lossy())]
+    MmapFailed {
+        filename: PathBuf,
+        size: u64,
+        source: std::io::Error,
+    },
+
+    #[error("Failed to lock {size} bytes of {} in memory: {source}. Check the RLIMIT_MEMLOCK limit, e.g. with `ulimit -l`.", .filename.to_string_lossy())]
+    LockMemoryFailed {
+        filename: PathBuf,
+        size: u64,
+        source: std::io::Error,
+    },
+
+    #[cfg(feature = "osmpbf")]
+    #[error(transparent)]
+    Pbf(#[from] osmpbf::Error),
+
+    #[error("Not enough disk space to grow {} to {requested_size} bytes: {source}", .path.to_string_lossy())]
+    DiskFull {
+        path: PathBuf,
+        requested_size: u64,
+        source: std::io::Error,
+    },
+
+    #[error("Index {0} is not in any of the shard ranges")]
+    NoShardForIndex(usize),
+
+    #[error("Cache lock is poisoned: another thread panicked while holding it")]
+    LockPoisoned,
+
     #[error("IO error: {0}")]
     Io(#[from] std::io::Error),
 
@@ -36,4 +127,12 @@ pub enum OsmNodeCacheError {
     BinCode(#[from] bincode::Error),
 }
 
+#[cfg(feature = "std")]
+impl<T> From<PoisonError<T>> for OsmNodeCacheError {
+    fn from(_: PoisonError<T>) -> Self {
+        Self::LockPoisoned
+    }
+}
+
+#[cfg(feature = "std")]
 pub type OsmNodeCacheResult<T> = Result<T, OsmNodeCacheError>;
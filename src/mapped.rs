@@ -0,0 +1,85 @@
+use crate::traits::Cache;
+use crate::OsmNodeCacheResult;
+
+/// An adapter that remaps each index with a function before passing it to the wrapped cache,
+/// e.g. to translate sparse OSM ids into dense internal ids.
+pub struct MappedCache<C: Cache, F: Fn(usize) -> usize> {
+    inner: C,
+    map_index: F,
+}
+
+impl<C: Cache, F: Fn(usize) -> usize> MappedCache<C, F> {
+    #[must_use]
+    pub fn new(inner: C, map_index: F) -> Self {
+        Self { inner, map_index }
+    }
+
+    /// Return the wrapped cache
+    #[must_use]
+    pub fn into_inner(self) -> C {
+        self.inner
+    }
+}
+
+impl<C: Cache, F: Fn(usize) -> usize> Cache for MappedCache<C, F> {
+    #[inline]
+    fn get(&self, index: usize) -> u64 {
+        self.inner.get((self.map_index)(index))
+    }
+
+    #[inline]
+    fn set(&mut self, index: usize, value: u64) {
+        let index = (self.map_index)(index);
+        self.inner.set(index, value);
+    }
+
+    #[inline]
+    fn try_set(&mut self, index: usize, value: u64) -> OsmNodeCacheResult<()> {
+        let index = (self.map_index)(index);
+        self.inner.try_set(index, value)
+    }
+
+    #[inline]
+    fn try_get(&self, index: usize) -> Option<u64> {
+        self.inner.try_get((self.map_index)(index))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::path::PathBuf;
+
+    use crate::*;
+
+    #[test]
+    fn mapped_cache() {
+        let mut cache = MappedCache::new(HashMapCache::new(), |i| i - 1000);
+        cache.set(1005, 50);
+        cache.set_lat_lon(1007, 1.0, 2.0);
+        assert_eq!(cache.get(1005), 50);
+        assert_eq!(cache.try_get(1006), None);
+        assert!(cache.try_get_lat_lon(1007).is_some());
+        let inner = cache.into_inner();
+        assert_eq!(inner.get(5), 50);
+        assert_eq!(inner.len(), 2);
+    }
+
+    #[test]
+    fn mapped_cache_accessor() {
+        let test_file = "./mapped_cache.dat";
+        let _ = fs::remove_file(test_file);
+        {
+            let fc = DenseFileCacheOpts::new(PathBuf::from(test_file))
+                .page_size(80)
+                .open()
+                .unwrap();
+            let mut cache = MappedCache::new(fc.get_accessor(), |i| i / 2);
+            cache.set(8, 40);
+            assert_eq!(cache.get(9), 40);
+            drop(cache);
+            assert_eq!(fc.reader().get(4), 40);
+        }
+        let _ = fs::remove_file(test_file);
+    }
+}
@@ -0,0 +1,101 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use crate::traits::Cache;
+
+/// A decorator counting the hits and misses of [`Cache::try_get`] calls on the wrapped cache,
+/// including the ones made by [`Cache::try_get_lat_lon`]. Other calls are passed through uncounted.
+pub struct CountingCache<C: Cache> {
+    inner: C,
+    hits: AtomicU64,
+    misses: AtomicU64,
+}
+
+impl<C: Cache> CountingCache<C> {
+    #[must_use]
+    pub fn new(inner: C) -> Self {
+        Self {
+            inner,
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+        }
+    }
+
+    /// Number of `(hits, misses)` counted so far
+    #[must_use]
+    pub fn stats(&self) -> (u64, u64) {
+        (
+            self.hits.load(Ordering::Relaxed),
+            self.misses.load(Ordering::Relaxed),
+        )
+    }
+
+    /// Return the wrapped cache
+    #[must_use]
+    pub fn into_inner(self) -> C {
+        self.inner
+    }
+}
+
+impl<C: Cache> Cache for CountingCache<C> {
+    #[inline]
+    fn get(&self, index: usize) -> u64 {
+        self.inner.get(index)
+    }
+
+    #[inline]
+    fn set(&mut self, index: usize, value: u64) {
+        self.inner.set(index, value);
+    }
+
+    #[inline]
+    fn try_get(&self, index: usize) -> Option<u64> {
+        let value = self.inner.try_get(index);
+        let counter = if value.is_some() {
+            &self.hits
+        } else {
+            &self.misses
+        };
+        counter.fetch_add(1, Ordering::Relaxed);
+        value
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::path::PathBuf;
+
+    use crate::*;
+
+    #[test]
+    fn counting_cache() {
+        let mut cache = CountingCache::new(HashMapCache::new());
+        cache.set(1, 10);
+        cache.set_lat_lon(2, 1.0, 2.0);
+        assert_eq!(cache.try_get(1), Some(10));
+        assert_eq!(cache.try_get(3), None);
+        assert!(cache.try_get_lat_lon(2).is_some());
+        assert_eq!(cache.get(4), 0);
+        assert_eq!(cache.stats(), (2, 1));
+        assert_eq!(cache.into_inner().len(), 2);
+    }
+
+    #[test]
+    fn counting_cache_accessor() {
+        let test_file = "./counting_cache.dat";
+        let _ = fs::remove_file(test_file);
+        {
+            let fc = DenseFileCacheOpts::new(PathBuf::from(test_file))
+                .page_size(80)
+                .open()
+                .unwrap();
+            let mut cache = CountingCache::new(fc.get_accessor());
+            cache.set(1, 10);
+            assert_eq!(cache.try_get(1), Some(10));
+            assert_eq!(cache.try_get(2), None);
+            assert_eq!(cache.try_get(1_000), None);
+            assert_eq!(cache.stats(), (1, 2));
+        }
+        let _ = fs::remove_file(test_file);
+    }
+}
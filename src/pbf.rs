@@ -0,0 +1,117 @@
+use std::path::Path;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use osmpbf::{BlobDecode, BlobReader};
+use rayon::iter::{ParallelBridge, ParallelIterator};
+
+use crate::traits::{Cache, CacheStore};
+use crate::OsmNodeCacheResult;
+
+/// Node counts of an OSM PBF file imported with [`import_nodes_from_pbf`]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct PbfImportStats {
+    /// Number of nodes stored in the cache
+    pub nodes: usize,
+    /// Number of nodes skipped because their id is negative, e.g. new nodes in files saved by JOSM
+    pub skipped: usize,
+}
+
+/// Store the coordinates of all nodes in an OSM PBF file with [`crate::Cache::set_lat_lon`],
+/// indexed by node id. Blobs are decoded in parallel.
+/// Nodes with a negative id cannot be used as an index, so they are skipped and counted
+/// instead of failing the whole import.
+pub fn import_nodes_from_pbf<P: AsRef<Path>>(
+    path: P,
+    cache: &(impl CacheStore + Sync),
+) -> OsmNodeCacheResult<PbfImportStats> {
+    let count = AtomicUsize::new(0);
+    let skipped = AtomicUsize::new(0);
+    BlobReader::from_path(path)?
+        .par_bridge()
+        .try_for_each(|blob| -> OsmNodeCacheResult<()> {
+            if let BlobDecode::OsmData(block) = blob?.decode()? {
+                let mut accessor = cache.get_accessor();
+                let mut stats = PbfImportStats::default();
+                for group in block.groups() {
+                    for node in group.dense_nodes() {
+                        stats.set(&mut *accessor, node.id(), node.lat(), node.lon());
+                    }
+                    for node in group.nodes() {
+                        stats.set(&mut *accessor, node.id(), node.lat(), node.lon());
+                    }
+                }
+                count.fetch_add(stats.nodes, Ordering::Relaxed);
+                skipped.fetch_add(stats.skipped, Ordering::Relaxed);
+            }
+            Ok(())
+        })?;
+    Ok(PbfImportStats {
+        nodes: count.into_inner(),
+        skipped: skipped.into_inner(),
+    })
+}
+
+impl PbfImportStats {
+    fn set(&mut self, cache: &mut dyn Cache, id: i64, lat: f64, lon: f64) {
+        if let Ok(index) = usize::try_from(id) {
+            cache.set_lat_lon(index, lat, lon);
+            self.nodes += 1;
+        } else {
+            self.skipped += 1;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::*;
+
+    fn assert_nodes(cache: &HashMapCache) {
+        assert_eq!(cache.len(), 3);
+        let (lat, lon) = cache.get_lat_lon(106);
+        assert!((lat - 52.119_923_5).abs() < 1e-6);
+        assert!((lon - 11.625_644_6).abs() < 1e-6);
+        assert!(cache.try_get(105).is_some());
+        assert!(cache.try_get(108).is_some());
+    }
+
+    #[test]
+    fn import_dense_nodes() {
+        let cache = HashMapCache::new();
+        let stats = import_nodes_from_pbf("testdata/test.osm.pbf", &cache).unwrap();
+        assert_eq!(stats.nodes, 3);
+        assert_nodes(&cache);
+    }
+
+    #[test]
+    fn import_nodes() {
+        let cache = HashMapCache::new();
+        let stats = import_nodes_from_pbf("testdata/test_nodense.osm.pbf", &cache).unwrap();
+        assert_eq!(stats.nodes, 3);
+        assert_nodes(&cache);
+    }
+
+    #[test]
+    fn import_negative_ids() {
+        let cache = HashMapCache::new();
+        let stats = import_nodes_from_pbf("testdata/test_negative.osm.pbf", &cache).unwrap();
+        assert_eq!(
+            stats,
+            PbfImportStats {
+                nodes: 1,
+                skipped: 2
+            }
+        );
+        assert_eq!(cache.len(), 1);
+        let (lat, lon) = cache.get_lat_lon(106);
+        assert!((lat - 52.119_923_5).abs() < 1e-6);
+        assert!((lon - 11.625_644_6).abs() < 1e-6);
+    }
+
+    #[test]
+    fn import_missing_file() {
+        let cache = HashMapCache::new();
+        let result = import_nodes_from_pbf("testdata/missing.osm.pbf", &cache);
+        assert!(matches!(result, Err(OsmNodeCacheError::Pbf(_))));
+    }
+}
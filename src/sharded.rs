@@ -0,0 +1,198 @@
+use std::ops::Range;
+
+use crate::traits::{Cache, CacheStore};
+use crate::{DenseFileCache, OsmNodeCacheError, OsmNodeCacheResult};
+
+/// A single logical cache over several dense files, each holding a range of indexes,
+/// e.g. to keep each file under a size limit or to spread them across disks.
+/// Each file stores its values starting at slot 0, i.e. index `range.start` is stored in slot 0.
+#[derive(Clone)]
+pub struct ShardedDenseCache {
+    /// Shards sorted by range start
+    shards: Vec<(Range<usize>, DenseFileCache)>,
+}
+
+struct ShardedDenseCacheAccessor<'a> {
+    ranges: Vec<Range<usize>>,
+    shards: Vec<Box<dyn Cache + 'a>>,
+}
+
+impl ShardedDenseCache {
+    /// Create a cache from `(index range, file)` shards, in any order.
+    /// Panics if any of the ranges overlap, or if two shards use the same file, e.g. clones of one cache.
+    /// Such shards would overwrite each other's values, and the accessor would hold the read lock
+    /// of their shared map twice, so growing one of them would deadlock.
+    #[must_use]
+    pub fn new(mut shards: Vec<(Range<usize>, DenseFileCache)>) -> Self {
+        shards.sort_by_key(|(range, _)| range.start);
+        for pair in shards.windows(2) {
+            assert!(
+                pair[0].0.end <= pair[1].0.start,
+                "Shard ranges {:?} and {:?} overlap",
+                pair[0].0,
+                pair[1].0
+            );
+        }
+        for (i, (range, shard)) in shards.iter().enumerate() {
+            for (other_range, other) in &shards[i + 1..] {
+                assert!(
+                    !shard.same_file(other),
+                    "Shard ranges {range:?} and {other_range:?} use the same file"
+                );
+            }
+        }
+        Self { shards }
+    }
+}
+
+impl CacheStore for ShardedDenseCache {
+    fn get_accessor(&self) -> Box<dyn Cache + '_> {
+        Box::new(ShardedDenseCacheAccessor {
+            ranges: self.shards.iter().map(|(r, _)| r.clone()).collect(),
+            shards: self.shards.iter().map(|(_, c)| c.get_accessor()).collect(),
+        })
+    }
+}
+
+impl<'a> ShardedDenseCacheAccessor<'a> {
+    /// Find the shard holding the index, and the index within that shard
+    fn locate(&self, index: usize) -> Option<(usize, usize)> {
+        let pos = self.ranges.partition_point(|r| r.start <= index);
+        let shard = pos.checked_sub(1)?;
+        let range = &self.ranges[shard];
+        if range.contains(&index) {
+            Some((shard, index - range.start))
+        } else {
+            None
+        }
+    }
+
+    fn locate_or_panic(&self, index: usize) -> (usize, usize) {
+        self.locate(index)
+            .unwrap_or_else(|| panic!("Index {index} is not in any of the shard ranges"))
+    }
+}
+
+impl<'a> Cache for ShardedDenseCacheAccessor<'a> {
+    /// Panics if the index is not in any of the shard ranges.
+    fn get(&self, index: usize) -> u64 {
+        let (shard, local) = self.locate_or_panic(index);
+        self.shards[shard].get(local)
+    }
+
+    /// Panics if the index is not in any of the shard ranges.
+    fn set(&mut self, index: usize, value: u64) {
+        let (shard, local) = self.locate_or_panic(index);
+        self.shards[shard].set(local, value);
+    }
+
+    /// Returns [`OsmNodeCacheError::NoShardForIndex`] if the index is not in any of the shard ranges.
+    fn try_set(&mut self, index: usize, value: u64) -> OsmNodeCacheResult<()> {
+        let (shard, local) = self
+            .locate(index)
+            .ok_or(OsmNodeCacheError::NoShardForIndex(index))?;
+        self.shards[shard].try_set(local, value)
+    }
+
+    /// Returns `None` if the index is not in any of the shard ranges.
+    fn try_get(&self, index: usize) -> Option<u64> {
+        let (shard, local) = self.locate(index)?;
+        self.shards[shard].try_get(local)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::path::PathBuf;
+
+    use crate::*;
+
+    fn open(test_file: &str) -> DenseFileCache {
+        let _ = fs::remove_file(test_file);
+        DenseFileCacheOpts::new(PathBuf::from(test_file))
+            .page_size(80)
+            .open()
+            .unwrap()
+    }
+
+    #[test]
+    fn sharded_test() {
+        let test_file1 = "./sharded_test1.dat";
+        let test_file2 = "./sharded_test2.dat";
+        {
+            let shard1 = open(test_file1);
+            let shard2 = open(test_file2);
+            let sharded = ShardedDenseCache::new(vec![
+                (1000..2000, shard2.clone()),
+                (0..1000, shard1.clone()),
+            ]);
+            let mut cache = sharded.get_accessor();
+            cache.set(5, 50);
+            cache.set(999, 9990);
+            cache.set(1000, 10000);
+            cache.set(1005, 10050);
+            assert_eq!(cache.get(5), 50);
+            assert_eq!(cache.get(999), 9990);
+            assert_eq!(cache.get(1000), 10000);
+            assert_eq!(cache.try_get(1005), Some(10050));
+            assert_eq!(cache.try_get(6), None);
+            assert_eq!(cache.try_get(2000), None);
+            drop(cache);
+
+            // Each shard stores values relative to its range start
+            assert_eq!(shard1.reader().len(), 1000);
+            assert_eq!(shard2.reader().get(5), 10050);
+            assert_eq!(shard2.reader().len(), 10);
+        }
+        let _ = fs::remove_file(test_file1);
+        let _ = fs::remove_file(test_file2);
+    }
+
+    #[test]
+    #[should_panic(expected = "Index 2000 is not in any of the shard ranges")]
+    fn sharded_out_of_range() {
+        let test_file = "./sharded_out_of_range.dat";
+        let sharded = ShardedDenseCache::new(vec![(1000..2000, open(test_file))]);
+        let _ = fs::remove_file(test_file);
+        sharded.get_accessor().set(2000, 1);
+    }
+
+    #[test]
+    fn sharded_try_set() {
+        let test_file = "./sharded_try_set.dat";
+        {
+            let sharded = ShardedDenseCache::new(vec![(1000..2000, open(test_file))]);
+            let mut cache = sharded.get_accessor();
+            cache.try_set(1005, 1).unwrap();
+            assert_eq!(cache.try_get(1005), Some(1));
+            assert!(matches!(
+                cache.try_set(2000, 1),
+                Err(OsmNodeCacheError::NoShardForIndex(2000))
+            ));
+            assert!(matches!(
+                cache.try_set(5, 1),
+                Err(OsmNodeCacheError::NoShardForIndex(5))
+            ));
+        }
+        let _ = fs::remove_file(test_file);
+    }
+
+    #[test]
+    #[should_panic(expected = "use the same file")]
+    fn sharded_same_file() {
+        let test_file = "./sharded_same_file.dat";
+        let shard = open(test_file);
+        let _ = fs::remove_file(test_file);
+        let _ = ShardedDenseCache::new(vec![(0..10, shard.clone()), (10..20, shard)]);
+    }
+
+    #[test]
+    #[should_panic(expected = "overlap")]
+    fn sharded_overlap() {
+        let test_file = "./sharded_overlap.dat";
+        let shard = open(test_file);
+        let _ = fs::remove_file(test_file);
+        let _ = ShardedDenseCache::new(vec![(0..10, shard.clone()), (5..20, shard)]);
+    }
+}
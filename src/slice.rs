@@ -0,0 +1,59 @@
+use crate::traits::Cache;
+
+/// A read-only cache over a borrowed slice of values, e.g. a small fixture for unit tests:
+/// `SliceCache(&[pack_lat_lon(1.0, 2.0), pack_lat_lon(3.0, 4.0)])`.
+/// Same as the dense file, the index is the position in the slice, and a zero value is reported as missing.
+#[derive(Clone, Copy, Debug)]
+pub struct SliceCache<'a>(pub &'a [u64]);
+
+impl Cache for SliceCache<'_> {
+    fn get(&self, index: usize) -> u64 {
+        assert!(
+            index < self.0.len(),
+            "Index {index} exceeds cache size {}",
+            self.0.len()
+        );
+        self.0[index]
+    }
+
+    fn try_get(&self, index: usize) -> Option<u64> {
+        match self.0.get(index)? {
+            0 => None,
+            v => Some(*v),
+        }
+    }
+
+    fn set(&mut self, _index: usize, _value: u64) {
+        panic!("SliceCache is read-only");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::encoding::pack_lat_lon;
+    use crate::{Cache, SliceCache};
+
+    #[test]
+    fn slice_cache() {
+        let cache = SliceCache(&[pack_lat_lon(1.0, 2.0), 0, 42]);
+        let c: &dyn Cache = &cache;
+        let (lat, lon) = c.get_lat_lon(0);
+        assert!((lat - 1.0).abs() < 1e-6 && (lon - 2.0).abs() < 1e-6);
+        assert_eq!(c.get(1), 0);
+        assert_eq!(c.try_get(1), None);
+        assert_eq!(c.try_get(2), Some(42));
+        assert_eq!(c.try_get(3), None);
+    }
+
+    #[test]
+    #[should_panic(expected = "exceeds cache size")]
+    fn slice_cache_out_of_range() {
+        let _ = SliceCache(&[1]).get(1);
+    }
+
+    #[test]
+    #[should_panic(expected = "read-only")]
+    fn slice_cache_set() {
+        SliceCache(&[1]).set(0, 2);
+    }
+}
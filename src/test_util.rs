@@ -0,0 +1,63 @@
+//! Assertion helpers for tests, e.g. to verify a migration between cache backends.
+
+use crate::traits::Cache;
+
+/// Assert that both caches have the same presence of values at each of the `ids`, and that the
+/// decoded latitudes and longitudes differ by at most `epsilon` degrees. Values are compared
+/// with [`Cache::try_get_lat_lon`], so the exact stored u64 values may differ.
+///
+/// # Panics
+/// Panics with the first mismatching id and both values.
+pub fn assert_coords_close(
+    a: &dyn Cache,
+    b: &dyn Cache,
+    ids: impl IntoIterator<Item = usize>,
+    epsilon: f64,
+) {
+    for id in ids {
+        match (a.try_get_lat_lon(id), b.try_get_lat_lon(id)) {
+            (None, None) => {}
+            (Some(va), Some(vb))
+                if (va.0 - vb.0).abs() <= epsilon && (va.1 - vb.1).abs() <= epsilon => {}
+            (va, vb) => {
+                panic!("Coordinates of id {id} differ by more than {epsilon}: {va:?} != {vb:?}")
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::test_util::assert_coords_close;
+    use crate::{Cache, HashMapCache};
+
+    #[test]
+    fn coords_close() {
+        let mut a = HashMapCache::new();
+        let mut b = HashMapCache::new();
+        a.set_lat_lon(1, 10.0, 20.0);
+        b.set_lat_lon(1, 10.000_000_1, 20.0);
+        a.set_lat_lon(2, -45.0, 170.0);
+        b.set_lat_lon(2, -45.0, 170.0);
+        assert_coords_close(&a, &b, 0..5, 1e-6);
+    }
+
+    #[test]
+    #[should_panic(expected = "Coordinates of id 1 differ")]
+    fn coords_far() {
+        let mut a = HashMapCache::new();
+        let mut b = HashMapCache::new();
+        a.set_lat_lon(1, 10.0, 20.0);
+        b.set_lat_lon(1, 10.1, 20.0);
+        assert_coords_close(&a, &b, [1], 1e-6);
+    }
+
+    #[test]
+    #[should_panic(expected = ") != None")]
+    fn coords_missing() {
+        let mut a = HashMapCache::new();
+        let b = HashMapCache::new();
+        a.set_lat_lon(3, 10.0, 20.0);
+        assert_coords_close(&a, &b, [3], 1e-6);
+    }
+}
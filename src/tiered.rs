@@ -0,0 +1,137 @@
+use crate::traits::{Cache, CacheStore};
+use crate::{DenseFileCache, HashMapCache};
+
+/// A two-level cache: reads check the in-memory `front` first and fall through to the `back` dense file,
+/// while all writes go to the `front` only.
+#[derive(Clone)]
+pub struct TieredCache {
+    front: HashMapCache,
+    back: DenseFileCache,
+}
+
+/// A write-overlay over an immutable dense file: values set through it are kept in memory,
+/// and the dense file is never modified or grown. See [`DenseFileCache::overlay`].
+pub type OverlayCache = TieredCache;
+
+struct TieredCacheAccessor<'a> {
+    front: HashMapCache,
+    back: Box<dyn Cache + 'a>,
+}
+
+impl TieredCache {
+    #[must_use]
+    pub fn new(front: HashMapCache, back: DenseFileCache) -> Self {
+        Self { front, back }
+    }
+}
+
+impl CacheStore for TieredCache {
+    fn get_accessor(&self) -> Box<dyn Cache + '_> {
+        Box::new(TieredCacheAccessor {
+            front: self.front.clone(),
+            back: self.back.get_accessor(),
+        })
+    }
+}
+
+impl<'a> Cache for TieredCacheAccessor<'a> {
+    /// Returns the default value of the `front` cache if neither cache holds the index,
+    /// see [`HashMapCache::with_default`].
+    fn get(&self, index: usize) -> u64 {
+        self.try_get(index)
+            .unwrap_or_else(|| self.front.default_value())
+    }
+
+    fn set(&mut self, index: usize, value: u64) {
+        self.front.set(index, value);
+    }
+
+    fn try_get(&self, index: usize) -> Option<u64> {
+        self.front
+            .try_get(index)
+            .or_else(|| self.back.try_get(index))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::path::PathBuf;
+
+    use crate::*;
+
+    #[test]
+    fn tiered_test() {
+        let test_file = "./tiered_test.dat";
+        let _ = fs::remove_file(test_file);
+        {
+            let back = DenseFileCacheOpts::new(PathBuf::from(test_file))
+                .page_size(8)
+                .open()
+                .unwrap();
+            back.get_accessor().set(1, 10);
+            back.get_accessor().set(2, 20);
+
+            let front = HashMapCache::new();
+            let tiered = TieredCache::new(front.clone(), back.clone());
+            let mut cache = tiered.get_accessor();
+            cache.set(2, 200);
+            cache.set(3, 300);
+            assert_eq!(cache.get(1), 10);
+            assert_eq!(cache.get(2), 200);
+            assert_eq!(cache.get(3), 300);
+            assert_eq!(cache.try_get(4), None);
+            assert_eq!(cache.get(1_000_000), 0);
+
+            // Writes never reach the dense file
+            assert_eq!(front.get(2), 200);
+            assert_eq!(back.get_accessor().get(2), 20);
+        }
+        let _ = fs::remove_file(test_file);
+    }
+
+    #[test]
+    fn tiered_default() {
+        let test_file = "./tiered_default.dat";
+        let _ = fs::remove_file(test_file);
+        {
+            let back = DenseFileCacheOpts::new(PathBuf::from(test_file))
+                .page_size(8)
+                .open()
+                .unwrap();
+            back.get_accessor().set(1, 10);
+            let tiered = TieredCache::new(HashMapCache::with_default(7), back);
+            let cache = tiered.get_accessor();
+            assert_eq!(cache.get(1), 10);
+            assert_eq!(cache.get(0), 7);
+            assert_eq!(cache.get(1_000_000), 7);
+            assert_eq!(cache.try_get(0), None);
+        }
+        let _ = fs::remove_file(test_file);
+    }
+
+    #[test]
+    fn overlay_test() {
+        let test_file = "./overlay_test.dat";
+        let _ = fs::remove_file(test_file);
+        {
+            let base = DenseFileCacheOpts::new(PathBuf::from(test_file))
+                .page_size(8)
+                .open()
+                .unwrap();
+            base.get_accessor().set(1, 10);
+
+            let overlay = base.overlay();
+            let mut cache = overlay.get_accessor();
+            cache.set(1, 100);
+            cache.set(1_000_000, 42);
+            assert_eq!(cache.get(1), 100);
+            assert_eq!(cache.get(1_000_000), 42);
+            drop(cache);
+
+            assert_eq!(base.get_accessor().get(1), 10);
+            assert_eq!(base.reader().len(), 2);
+        }
+        let _ = fs::remove_file(test_file);
+    }
+}
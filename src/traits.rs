@@ -1,12 +1,16 @@
 use std::fs::{File, OpenOptions};
+use std::ops::Range;
 use std::path::Path;
 
+use crate::encoding::{
+    pack_i32s, pack_lat_lon, pack_lat_lon_with, pack_u32s, unpack_i32s, unpack_lat_lon,
+    unpack_lat_lon_clamped, unpack_u32s, RoundingMode,
+};
 use crate::{OsmNodeCacheError, OsmNodeCacheResult};
 
-const LAT_I32_RATE: f64 = i32::MAX as f64 / 90_f64;
-const I32_LAT_RATE: f64 = 1_f64 / LAT_I32_RATE;
-const LON_I32_RATE: f64 = i32::MAX as f64 / 180_f64;
-const I32_LON_RATE: f64 = 1_f64 / LON_I32_RATE;
+/// OSM node id, to keep node ids apart from the cached values at the type level
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct NodeId(pub u64);
 
 pub trait CacheStore {
     /// Create a thread-safe caching accessor
@@ -17,216 +21,296 @@ pub trait Cache {
     fn get(&self, index: usize) -> u64;
     fn set(&mut self, index: usize, value: u64);
 
+    /// Get the value at index, or `None` if the cache has no value stored there.
+    #[inline]
+    fn try_get(&self, index: usize) -> Option<u64> {
+        Some(self.get(index))
+    }
+
+    /// Get all values in the index range, e.g. the nodes of a contiguous id range.
+    /// Same as [`get`](Self::get), indexes without a value may panic, depending on the cache.
+    fn get_range(&self, range: Range<usize>) -> Vec<u64> {
+        range.map(|index| self.get(index)).collect()
+    }
+
+    /// Same as [`get_range`](Self::get_range), but decodes the values as latitude/longitude.
+    fn get_range_lat_lon(&self, range: Range<usize>) -> Vec<(f64, f64)> {
+        self.get_range(range)
+            .into_iter()
+            .map(unpack_lat_lon)
+            .collect()
+    }
+
+    /// Set the value only if it differs from the stored one, and return whether it was written,
+    /// e.g. to avoid taking a shard lock when re-importing identical values into a hashmap.
+    /// The read and the write are not atomic, so a concurrent `set` of the same index
+    /// between them may be overwritten.
+    #[inline]
+    fn set_if_changed(&mut self, index: usize, value: u64) -> bool {
+        if self.try_get(index) == Some(value) {
+            false
+        } else {
+            self.set(index, value);
+            true
+        }
+    }
+
+    /// Same as [`set`](Self::set), but returns an error instead of panicking if the cache could not grow
+    /// to fit the index, e.g. [`OsmNodeCacheError::DiskFull`] to free some space and retry.
+    #[inline]
+    fn try_set(&mut self, index: usize, value: u64) -> OsmNodeCacheResult<()> {
+        self.set(index, value);
+        Ok(())
+    }
+
+    /// Same as [`set`](Self::set), but if the cache has to grow to fit the index,
+    /// make room for `index + reserve_ahead` at once, e.g. when importing ascending ids
+    /// where the extent of the next batch is known. Caches that never grow ignore `reserve_ahead`.
+    #[inline]
+    fn set_and_reserve(&mut self, index: usize, value: u64, reserve_ahead: usize) {
+        let _ = reserve_ahead;
+        self.set(index, value);
+    }
+
+    /// Same as [`get`](Self::get), but indexed by node id
+    #[inline]
+    fn get_node(&self, id: NodeId) -> u64 {
+        self.get(id.0 as usize)
+    }
+
+    /// Same as [`set`](Self::set), but indexed by node id
+    #[inline]
+    fn set_node(&mut self, id: NodeId, value: u64) {
+        self.set(id.0 as usize, value);
+    }
+
+    /// Get two u32 values packed into the u64 value at index, e.g. tile x/y
+    #[inline]
+    fn get_pair(&self, index: usize) -> (u32, u32) {
+        unpack_u32s(self.get(index))
+    }
+
+    /// Store two u32 values by packing them into a single u64 value
+    #[inline]
+    fn set_pair(&mut self, index: usize, a: u32, b: u32) {
+        self.set(index, pack_u32s(a, b));
+    }
+
     /// Get latitude/longitude by decoding them from the u64 value treated as two packed i32 values.
     #[inline]
     fn get_lat_lon(&self, index: usize) -> (f64, f64) {
-        let (lat, lon) = u64_to_i32s(self.get(index));
-        (i32_to_latitude(lat), i32_to_longitude(lon))
+        unpack_lat_lon(self.get(index))
+    }
+
+    /// Same as [`get_lat_lon`](Self::get_lat_lon), but never returns coordinates outside of the valid
+    /// latitude and longitude ranges, see [`i32_to_latitude_clamped`](crate::encoding::i32_to_latitude_clamped).
+    #[inline]
+    fn get_lat_lon_clamped(&self, index: usize) -> (f64, f64) {
+        unpack_lat_lon_clamped(self.get(index))
+    }
+
+    /// Same as [`get_lat_lon`](Self::get_lat_lon), but returns `None` if the cache has no value at index.
+    #[inline]
+    fn try_get_lat_lon(&self, index: usize) -> Option<(f64, f64)> {
+        self.try_get(index).map(unpack_lat_lon)
     }
 
     /// Store latitude/longitude by encoding them as two i32 values, normalized on (-180..180) and (-90..90) ranges.
     #[inline]
     fn set_lat_lon(&mut self, index: usize, lat: f64, lon: f64) {
-        self.set(
-            index,
-            i32s_to_u64(latitude_to_i32(lat), longitude_to_i32(lon)),
-        );
+        self.set(index, pack_lat_lon(lat, lon));
     }
-}
 
-#[inline]
-fn latitude_to_i32(value: f64) -> i32 {
-    if (-90_f64..=90_f64).contains(&value) {
-        (value * LAT_I32_RATE) as i32
-    } else {
-        panic!("Invalid latitude {value}")
+    /// Get latitude/longitude as the stored fixed-point values, without converting them to `f64`.
+    /// Convert them to degrees with [`i32_to_latitude`](crate::encoding::i32_to_latitude)
+    /// and [`i32_to_longitude`](crate::encoding::i32_to_longitude).
+    #[inline]
+    fn get_lat_lon_i32(&self, index: usize) -> (i32, i32) {
+        unpack_i32s(self.get(index))
     }
-}
 
-#[inline]
-fn i32_to_latitude(value: i32) -> f64 {
-    (value as f64) * I32_LAT_RATE
-}
+    /// Store latitude/longitude given as fixed-point values, e.g. read with [`get_lat_lon_i32`](Self::get_lat_lon_i32)
+    #[inline]
+    fn set_lat_lon_i32(&mut self, index: usize, lat: i32, lon: i32) {
+        self.set(index, pack_i32s(lat, lon));
+    }
 
-#[inline]
-fn longitude_to_i32(value: f64) -> i32 {
-    if (-180_f64..=180_f64).contains(&value) {
-        (value * LON_I32_RATE) as i32
-    } else {
-        // experimental
-        f64::round(((value + 180_f64) % 360_f64 - 180_f64) * LON_I32_RATE) as i32
+    /// Same as [`set_lat_lon`](Self::set_lat_lon), but with the given rounding mode,
+    /// e.g. to match the output of a tool that rounds to the nearest value.
+    #[inline]
+    fn set_lat_lon_with(&mut self, index: usize, lat: f64, lon: f64, mode: RoundingMode) {
+        self.set(index, pack_lat_lon_with(lat, lon, mode));
     }
-}
 
-#[inline]
-fn i32_to_longitude(value: i32) -> f64 {
-    (value as f64) * I32_LON_RATE
+    /// Store a batch of `(index, lat, lon)` values, e.g. the nodes of a decoded PBF dense-node block.
+    /// Caches that grow may make room for the largest index at once.
+    #[inline]
+    fn set_many_lat_lon(&mut self, items: &[(usize, f64, f64)]) {
+        for &(index, lat, lon) in items {
+            self.set_lat_lon(index, lat, lon);
+        }
+    }
 }
 
-#[inline]
-fn u64_to_i32s(value: u64) -> (i32, i32) {
-    ((value >> 32) as i32, value as i32)
-}
+/// Allows wrapping cache accessors, e.g. the ones returned by [`CacheStore::get_accessor`].
+impl<C: Cache + ?Sized> Cache for Box<C> {
+    #[inline]
+    fn get(&self, index: usize) -> u64 {
+        (**self).get(index)
+    }
 
-#[inline]
-fn i32s_to_u64(high: i32, low: i32) -> u64 {
-    (high as u32 as u64) << 32 | (low as u32 as u64)
-}
+    #[inline]
+    fn set(&mut self, index: usize, value: u64) {
+        (**self).set(index, value);
+    }
 
-pub fn open_cache_file<P: AsRef<Path>>(filename: P) -> OsmNodeCacheResult<File> {
-    let file = OpenOptions::new()
-        .read(true)
-        .write(true)
-        .create(true)
-        .open(filename.as_ref())
-        .map_err(|e| OsmNodeCacheError::InvalidCacheFile(filename.as_ref().to_path_buf(), e))?;
-    Ok(file)
-}
+    #[inline]
+    fn try_get(&self, index: usize) -> Option<u64> {
+        (**self).try_get(index)
+    }
 
-#[cfg(test)]
-pub mod tests {
-    use std::panic;
-    use std::panic::{catch_unwind, UnwindSafe};
+    fn get_range(&self, range: Range<usize>) -> Vec<u64> {
+        (**self).get_range(range)
+    }
 
-    use rand::seq::SliceRandom;
-    use rand::thread_rng;
+    fn get_range_lat_lon(&self, range: Range<usize>) -> Vec<(f64, f64)> {
+        (**self).get_range_lat_lon(range)
+    }
 
-    use crate::traits::{
-        i32_to_latitude, i32_to_longitude, i32s_to_u64, latitude_to_i32, longitude_to_i32,
-        u64_to_i32s,
-    };
+    #[inline]
+    fn set_if_changed(&mut self, index: usize, value: u64) -> bool {
+        (**self).set_if_changed(index, value)
+    }
 
-    const EPSILON: f64 = f32::EPSILON as f64;
+    #[inline]
+    fn set_and_reserve(&mut self, index: usize, value: u64, reserve_ahead: usize) {
+        (**self).set_and_reserve(index, value, reserve_ahead);
+    }
 
-    fn eq(a: f64, b: f64) -> bool {
-        (a - b).abs() > EPSILON
+    #[inline]
+    fn get_node(&self, id: NodeId) -> u64 {
+        (**self).get_node(id)
     }
 
-    fn assert_floats(expected: f64, actual: f64) {
-        if eq(expected, actual) {
-            panic!(
-                "Assert failed: expected={expected}, actual={actual}, delta={}",
-                (expected - actual).abs()
-            );
-        }
+    #[inline]
+    fn set_node(&mut self, id: NodeId, value: u64) {
+        (**self).set_node(id, value);
     }
 
-    macro_rules! test_lat {
-        ( $stored:expr, $value:expr, $expected:expr ) => {
-            assert_eq!($stored, latitude_to_i32($value));
-            assert_floats($expected, i32_to_latitude(latitude_to_i32($value)));
-        };
-        ( $stored:expr, $value:expr ) => {
-            assert_eq!($stored, latitude_to_i32($value));
-            assert_floats($value, i32_to_latitude(latitude_to_i32($value)));
-        };
-    }
-    macro_rules! test_lon {
-        ( $stored:expr, $value:expr, $expected:expr ) => {
-            assert_eq!($stored, longitude_to_i32($value));
-            assert_floats($expected, i32_to_longitude(longitude_to_i32($value)));
-        };
-        ( $stored:expr, $value:expr ) => {
-            assert_eq!($stored, longitude_to_i32($value));
-            assert_floats($value, i32_to_longitude(longitude_to_i32($value)));
-        };
-    }
-
-    fn assert_panic<F: FnOnce() -> R + UnwindSafe, R: std::fmt::Debug>(f: F) {
-        let handler = panic::take_hook();
-        panic::set_hook(Box::new(|_| {}));
-        let res = catch_unwind(f);
-        panic::set_hook(handler);
-        assert!(
-            res.is_err(),
-            "Expected a panic, but received {:?} instead",
-            res.unwrap()
-        );
+    #[inline]
+    fn get_pair(&self, index: usize) -> (u32, u32) {
+        (**self).get_pair(index)
     }
 
-    #[test]
-    fn test_latitude() {
-        test_lat!(0, 0.0);
-        test_lat!(0, 0.00000001);
-        test_lat!(0, -0.00000001);
-        test_lat!(2, 0.0000001);
-        test_lat!(-2, -0.0000001);
-        test_lat!(23, 0.000001);
-        test_lat!(-23, -0.000001);
-        test_lat!(238, 0.00001);
-        test_lat!(-238, -0.00001);
-        test_lat!(2386, 0.0001);
-        test_lat!(-2386, -0.0001);
-        test_lat!(23860, 0.001);
-        test_lat!(-23860, -0.001);
-        test_lat!(238609, 0.01);
-        test_lat!(2386092, 0.1);
-        test_lat!(23860929, 1.0);
-        test_lat!(2147483408, 89.99999);
-        test_lat!(2147483623, 89.999999);
-        test_lat!(2147483644, 89.9999999);
-        test_lat!(2147483646, 89.99999999);
-        test_lat!(2147483646, 89.999999999);
-        test_lat!(2147483644, 90_f64 - EPSILON);
-        test_lat!(-2147483644, -90_f64 + EPSILON);
-        test_lat!(2147483647, 90.0);
-
-        assert_panic(|| latitude_to_i32(90_f64 + EPSILON));
-        assert_panic(|| latitude_to_i32(-90_f64 - EPSILON));
+    #[inline]
+    fn set_pair(&mut self, index: usize, a: u32, b: u32) {
+        (**self).set_pair(index, a, b);
     }
 
-    #[test]
-    fn test_longitude() {
-        test_lon!(0, 0.0);
-        test_lon!(0, 0.00000001);
-        test_lon!(0, -0.00000001);
-        test_lon!(1, 0.0000001);
-        test_lon!(-1, -0.0000001);
-        test_lon!(23, 0.000002);
-        test_lon!(-23, -0.000002);
-        test_lon!(119, 0.00001);
-        test_lon!(-119, -0.00001);
-        test_lon!(1193, 0.0001);
-        test_lon!(-1193, -0.0001);
-        test_lon!(11930, 0.001);
-        test_lon!(-11930, -0.001);
-        test_lon!(119304, 0.01);
-        test_lon!(1193046, 0.1);
-        test_lon!(11930464, 1.0);
-        test_lon!(2147483527, 179.99999);
-        test_lon!(2147483635, 179.999999);
-        test_lon!(2147483645, 179.9999999);
-        test_lon!(2147483646, 179.99999999);
-        test_lon!(2147483646, 179.999999999);
-        test_lon!(2147483647, 180.0);
-        test_lon!(-2147483647, 180.00000001, -180.0);
-        test_lon!(-2147483646, 180.0000001, -180.0);
-        test_lon!(-1908874353, 200.0, -160.0);
-    }
-
-    macro_rules! test_pack {
-        ( $high:expr, $low:expr ) => {{
-            let (high, low) = u64_to_i32s(i32s_to_u64($high, $low));
-            assert_eq!((high, low), ($high, $low));
-        }};
+    #[inline]
+    fn get_lat_lon(&self, index: usize) -> (f64, f64) {
+        (**self).get_lat_lon(index)
     }
 
-    #[test]
-    fn test_pack() {
-        test_pack!(0, 0);
-        test_pack!(1, 2);
-        test_pack!(2, 1);
-        test_pack!(-1, 1);
-        test_pack!(1, -1);
-        test_pack!(i32::MAX, -1);
-        test_pack!(-1, i32::MAX);
-        test_pack!(i32::MAX, i32::MIN);
-        test_pack!(i32::MIN, i32::MAX);
+    #[inline]
+    fn try_get_lat_lon(&self, index: usize) -> Option<(f64, f64)> {
+        (**self).try_get_lat_lon(index)
+    }
+
+    #[inline]
+    fn set_lat_lon(&mut self, index: usize, lat: f64, lon: f64) {
+        (**self).set_lat_lon(index, lat, lon);
     }
 
+    #[inline]
+    fn set_lat_lon_with(&mut self, index: usize, lat: f64, lon: f64, mode: RoundingMode) {
+        (**self).set_lat_lon_with(index, lat, lon, mode);
+    }
+
+    #[inline]
+    fn try_set(&mut self, index: usize, value: u64) -> OsmNodeCacheResult<()> {
+        (**self).try_set(index, value)
+    }
+
+    #[inline]
+    fn set_many_lat_lon(&mut self, items: &[(usize, f64, f64)]) {
+        (**self).set_many_lat_lon(items);
+    }
+}
+
+pub fn open_cache_file<P: AsRef<Path>>(filename: P, create: bool) -> OsmNodeCacheResult<File> {
+    let file = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create(create)
+        .truncate(false)
+        .open(filename.as_ref())
+        .map_err(|e| OsmNodeCacheError::InvalidCacheFile(filename.as_ref().to_path_buf(), e))?;
+    Ok(file)
+}
+
+/// Format a number with the largest unit it reaches, e.g. `7.5B` or `60GB`,
+/// keeping one decimal for scaled values below 10.
+pub(crate) fn format_scaled(value: u64, step: f64, units: &[&str]) -> String {
+    let mut scaled = value as f64;
+    let mut unit = 0;
+    while scaled >= step && unit + 1 < units.len() {
+        scaled /= step;
+        unit += 1;
+    }
+    if unit > 0 && scaled < 10.0 {
+        format!("{scaled:.1}{}", units[unit])
+    } else {
+        format!("{scaled:.0}{}", units[unit])
+    }
+}
+
+/// Format a count of elements, e.g. `7.5B`
+pub(crate) fn format_count(count: u64) -> String {
+    format_scaled(count, 1000.0, &["", "K", "M", "B", "T"])
+}
+
+/// Format a size in bytes using binary multiples, e.g. `60GB`
+pub(crate) fn format_bytes(bytes: u64) -> String {
+    format_scaled(bytes, 1024.0, &["B", "KB", "MB", "GB", "TB"])
+}
+
+#[cfg(test)]
+pub mod tests {
+    use rand::seq::SliceRandom;
+    use rand::thread_rng;
+
+    use super::{format_bytes, format_count};
+    use crate::encoding::{latitude_to_i32, longitude_to_i32};
+    use crate::{Cache, HashMapCache};
+
     pub(crate) fn get_random_items(items: usize) -> Vec<usize> {
         let mut vec: Vec<usize> = (0_usize..items).collect();
         vec.shuffle(&mut thread_rng());
         vec
     }
+
+    #[test]
+    fn human_sizes() {
+        assert_eq!(format_count(5), "5");
+        assert_eq!(format_count(7_500_000_000), "7.5B");
+        assert_eq!(format_count(12_345), "12K");
+        assert_eq!(format_bytes(830), "830B");
+        assert_eq!(format_bytes(60 * 1024 * 1024 * 1024), "60GB");
+        assert_eq!(format_bytes(1536), "1.5KB");
+    }
+
+    #[test]
+    fn lat_lon_i32() {
+        let mut cache = HashMapCache::new();
+        cache.set_lat_lon_i32(1, -900_000_000, 1_799_999_999);
+        assert_eq!(cache.get_lat_lon_i32(1), (-900_000_000, 1_799_999_999));
+        cache.set_lat_lon_i32(3, i32::MIN, i32::MIN);
+        assert_eq!(cache.get_lat_lon_clamped(3), (-90.0, -180.0));
+        cache.set_lat_lon(2, 51.5, -0.12);
+        assert_eq!(
+            cache.get_lat_lon_i32(2),
+            (latitude_to_i32(51.5), longitude_to_i32(-0.12))
+        );
+    }
 }